@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::error::OrderError;
+use crate::order::{OrderMngr, RawData, SendWithMngr};
+use crate::page::Paginated;
+use crate::price::Price;
+use crate::table::{deserialize_ftx_datetime, ContractSpec, ContractSpecTable, OptionContractSpec};
+use crate::ws::{
+    CollateralBalances, PositionUpdate, RawCollateralBalances, RawPosition, SanitizableMsg,
+};
+
+/// an open position, joined to its contract's spec
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub contract_id: u64,
+    /// signed, negative sizes are short positions
+    pub size: i64,
+    pub assigned_size: i64,
+    pub exercised_size: i64,
+    /// `None` if the contract isn't in the spec table, e.g. it was listed after the table was built
+    pub spec: Option<Rc<ContractSpec>>,
+}
+
+impl Position {
+    /// joins a position (from REST or an `open_positions_update`) to `specs`
+    pub fn from_update(update: PositionUpdate, specs: &ContractSpecTable) -> Self {
+        Position {
+            spec: specs.id_table.get(&update.contract_id).cloned(),
+            contract_id: update.contract_id,
+            size: update.size,
+            assigned_size: update.assigned_size,
+            exercised_size: update.exercised_size,
+        }
+    }
+    pub fn option(&self) -> Option<&OptionContractSpec> {
+        self.spec.as_deref().and_then(|s| s.as_opt_ref())
+    }
+    pub fn underlying(&self) -> Option<&str> {
+        match self.spec.as_deref()? {
+            ContractSpec::Option(o) => Some(&o.underlying),
+            ContractSpec::Future(f) => Some(&f.0.underlying_asset),
+            ContractSpec::Swap(s) => Some(&s.0.underlying_asset),
+        }
+    }
+}
+
+/// one asset's balance, in the asset's smallest unit (cents, satoshis, ...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollateralBalance {
+    pub asset: String,
+    pub available: i64,
+    /// held against open positions
+    pub position_locked: i64,
+}
+
+impl CollateralBalance {
+    pub fn total(&self) -> i64 {
+        self.available + self.position_locked
+    }
+    /// one entry per asset in either map, sorted by asset
+    pub fn from_balances(balances: &CollateralBalances) -> Vec<Self> {
+        let mut assets: Vec<&String> = balances
+            .available
+            .keys()
+            .chain(balances.position_locked.keys())
+            .collect();
+        assets.sort();
+        assets.dedup();
+
+        assets
+            .into_iter()
+            .map(|a| CollateralBalance {
+                asset: a.clone(),
+                available: balances.available.get(a).copied().unwrap_or(0),
+                position_locked: balances.position_locked.get(a).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+/// `GET /positions`, joined to the wrapped spec table
+pub struct Positions<'a>(pub &'a ContractSpecTable);
+
+impl<'a> SendWithMngr for Positions<'a> {
+    type OkType = Vec<Position>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Vec<Position>, OrderError> {
+        let resp = mngr.get(&format!("{}/positions", mngr.base_url()))?;
+        let raw: RawData<Vec<RawPosition>> = serde_json::from_reader(resp.into_reader())?;
+        Ok(raw
+            .data
+            .into_iter()
+            .map(|p| Position::from_update(p.sanitize(), self.0))
+            .collect())
+    }
+}
+
+/// `GET /collateral/balances`
+pub struct Balances;
+
+impl SendWithMngr for Balances {
+    type OkType = Vec<CollateralBalance>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Vec<CollateralBalance>, OrderError> {
+        let resp = mngr.get(&format!("{}/collateral/balances", mngr.base_url()))?;
+        let raw: RawData<RawCollateralBalances> = serde_json::from_reader(resp.into_reader())?;
+        Ok(CollateralBalance::from_balances(&raw.data.sanitize()))
+    }
+}
+
+/// one of our fills
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub id: String,
+    /// the order that was filled
+    pub mid: String,
+    pub contract_id: u64,
+    pub is_ask: bool,
+    pub price: Price,
+    pub size: u64,
+    /// in cents, negative for rebates
+    pub fee: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawFill {
+    id: String,
+    mid: String,
+    contract_id: u64,
+    is_ask: bool,
+    filled_price: u64,
+    filled_size: u64,
+    #[serde(default)]
+    fee: i64,
+    #[serde(deserialize_with = "deserialize_ftx_datetime")]
+    created_time: DateTime<Utc>,
+}
+
+impl<'a> SanitizableMsg<'a> for RawFill {
+    type OUT = Fill;
+    fn sanitize(self) -> Self::OUT {
+        Fill {
+            id: self.id,
+            mid: self.mid,
+            contract_id: self.contract_id,
+            is_ask: self.is_ask,
+            price: Price::from_cents(self.filled_price),
+            size: self.filled_size,
+            fee: self.fee,
+            timestamp: self.created_time,
+        }
+    }
+}
+
+impl<'a> OrderMngr<'a> {
+    /// every fill on the account, newest first, paged through `GET /trades` as it's iterated
+    pub fn fills(&self) -> Paginated<'_, 'a, RawFill> {
+        Paginated::new(self, "/trades")
+    }
+    /// `fills`, limited to one contract
+    pub fn fills_for(&self, contract_id: u64) -> Paginated<'_, 'a, RawFill> {
+        Paginated::new(self, &format!("/trades?contract_id={}", contract_id))
+    }
+    pub fn positions(&self, specs: &ContractSpecTable) -> Result<Vec<Position>, OrderError> {
+        self.send(&Positions(specs))
+    }
+    pub fn collateral_balances(&self) -> Result<Vec<CollateralBalance>, OrderError> {
+        self.send(&Balances)
+    }
+}
+
+/// net position size per underlying, in contracts
+pub fn net_size_by_underlying(positions: &[Position]) -> HashMap<String, i64> {
+    let mut out = HashMap::new();
+    for p in positions {
+        if let Some(u) = p.underlying() {
+            *out.entry(u.to_string()).or_insert(0) += p.size;
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{spec_table, MockServer};
+    use crate::retry::RetryPolicy;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn positions_and_balances() {
+        let server = MockServer::start(|req| {
+            match req.url.as_str() {
+            "/positions" => (
+                200,
+                r#"{"data": [{"contract_id": 1, "size": -5, "assigned_size": 2}, {"contract_id": 4, "size": 3}, {"contract_id": 77, "size": 1}]}"#
+                    .to_string(),
+            ),
+            "/collateral/balances" => (
+                200,
+                r#"{"data": {"collateral": {"available_balances": {"USD": 120000, "BTC": 25000000}, "position_locked_balances": {"USD": 23500, "ETH": 10}}}}"#
+                    .to_string(),
+            ),
+            _ => (404, "{}".to_string()),
+        }
+        });
+        let om = OrderMngr::new(&server.url, "key");
+        let specs = spec_table();
+
+        let pos = om.positions(&specs).unwrap();
+        assert_eq!(pos.len(), 3);
+        assert_eq!(pos[0].option().unwrap().label, "BTC-Mini-1");
+        assert_eq!(pos[0].assigned_size, 2);
+        assert!(pos[2].spec.is_none());
+        assert_eq!(net_size_by_underlying(&pos)["CBTC"], -2);
+
+        let bals = om.collateral_balances().unwrap();
+        let assets: Vec<&str> = bals.iter().map(|b| b.asset.as_str()).collect();
+        assert_eq!(assets, ["BTC", "ETH", "USD"]);
+        assert_eq!(bals[2].total(), 143500);
+        assert_eq!(bals[1].available, 0);
+    }
+
+    fn fill(id: u64) -> String {
+        format!(
+            r#"{{"id": "f{id}", "mid": "m{id}", "contract_id": 1, "is_ask": false, "filled_price": 175, "filled_size": 2, "fee": 30, "created_time": "2022-03-01 15:04:05+0000"}}"#
+        )
+    }
+
+    #[test]
+    fn fills_follow_cursors_and_offsets() {
+        let busy = AtomicBool::new(true);
+        let server = MockServer::start(move |req| {
+            let page = |fills: &[u64], meta: &str| {
+                let rows: Vec<String> = fills.iter().map(|i| fill(*i)).collect();
+                (
+                    200,
+                    format!(r#"{{"meta": {meta}, "data": [{}]}}"#, rows.join(",")),
+                )
+            };
+            match req.url.as_str() {
+                // cursor links, the second one relative to the host
+                "/trades?limit=200" => page(&[1, 2], r#"{"next": "/trades?after=f2&limit=200"}"#),
+                "/trades?after=f2&limit=200" => page(&[3], r#"{"next": null}"#),
+                // offsets
+                "/trades?contract_id=1&limit=200" => page(&[1, 2], r#"{"total_count": 3}"#),
+                "/trades?contract_id=1&limit=200&offset=2" => page(&[3], r#"{"total_count": 3}"#),
+                "/trades?contract_id=2&limit=200" => (
+                    200,
+                    format!(r#"{{"data": [{}, {{"id": "bad"}}]}}"#, fill(1)),
+                ),
+                // busy the first time round
+                "/trades?contract_id=3&limit=200" if busy.swap(false, Ordering::SeqCst) => {
+                    (503, r#"{"error": "busy"}"#.to_string())
+                }
+                "/trades?contract_id=3&limit=200" => page(&[1], r#"{"total_count": 1}"#),
+                _ => (404, "{}".to_string()),
+            }
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let fills: Vec<Fill> = om.fills().collect::<Result<_, _>>().unwrap();
+        let ids: Vec<&str> = fills.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(ids, ["f1", "f2", "f3"]);
+        assert_eq!(fills[0].price, Price::from_cents(175));
+        assert_eq!(fills[0].fee, 30);
+        assert_eq!(fills[0].timestamp.to_rfc3339(), "2022-03-01T15:04:05+00:00");
+
+        assert_eq!(om.fills_for(1).count(), 3);
+
+        let out: Vec<_> = om.fills_for(2).collect();
+        assert_eq!(out.len(), 1);
+        assert!(matches!(out[0], Err(OrderError::MalformedResponse(_))));
+
+        // pages are retried like any other read
+        om.retry_policy(Some(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }));
+        assert_eq!(om.fills_for(3).filter(|f| f.is_ok()).count(), 1);
+    }
+}
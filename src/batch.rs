@@ -0,0 +1,236 @@
+//! batches of orders, edits and cancels sent concurrently from the sync `OrderMngr`, plus
+//! cancel/replace of a quote ladder
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::error::OrderError;
+use crate::journal::JournalAction;
+use crate::order::{Cancel, Order, OrderEdit, OrderMngr, OrderResponse, SendWithMngr};
+
+/// most requests a batch has in flight at once
+pub const MAX_IN_FLIGHT: usize = 8;
+
+// a worker's result, `None` until some worker has sent the action
+type Slot<T> = Option<Result<T, OrderError>>;
+
+/// one level of `cancel_replace`
+#[derive(Debug)]
+pub struct Replaced {
+    pub cancel: Result<(), OrderError>,
+    /// `None` if the replacement wasn't sent because its cancel failed
+    pub order: Option<Result<OrderResponse, OrderError>>,
+}
+
+impl<'a> OrderMngr<'a> {
+    /// sends every order concurrently, results are in the same order as `ords`
+    pub fn send_orders(&mut self, ords: &[Order]) -> Vec<Result<OrderResponse, OrderError>> {
+        let out = self.send_batch(
+            ords,
+            |o| JournalAction::Order(o.clone()),
+            |o| Some(o.order_id.as_str()),
+        );
+        for (ord, resp) in ords.iter().zip(&out) {
+            if let Ok(resp) = resp {
+                self.append(resp, ord);
+            }
+        }
+        // every claimed mid is tracked by now
+        self.claimed.get_mut().unwrap().clear();
+        return out;
+    }
+    /// sends every edit concurrently, results are in the same order as `edits`
+    pub fn send_edits(&mut self, edits: &[OrderEdit]) -> Vec<Result<(), OrderError>> {
+        self.send_batch(edits, |e| JournalAction::Edit(e.clone()), |_| None)
+    }
+    /// sends every cancel concurrently, results are in the same order as `cancels`
+    pub fn send_cancels(&mut self, cancels: &[Cancel]) -> Vec<Result<(), OrderError>> {
+        self.send_batch(cancels, |c| JournalAction::Cancel(c.clone()), |_| None)
+    }
+
+    /// cancels every open order sent with `Order::client_tag` set to `tag`
+    pub fn cancel_tagged(&mut self, tag: &str) -> Vec<(String, Result<(), OrderError>)> {
+        let (mids, cancels): (Vec<String>, Vec<Cancel>) = self
+            .orders
+            .tagged(tag)
+            .filter(|o| o.is_open())
+            .map(|o| (o.mid.clone(), Cancel::one(o.mid.clone(), o.contract_id)))
+            .unzip();
+        let out = self.send_cancels(&cancels);
+        mids.into_iter().zip(out).collect()
+    }
+
+    /// swaps out a quote ladder level by level: every cancel goes out first, then each level's
+    /// new order is placed only once its old one is known to be gone (cancelled, or already
+    /// filled/cancelled on the exchange). a level whose cancel failed keeps its old order and
+    /// never gets a second one, so we can't end up quoting both.
+    pub fn cancel_replace(&mut self, ladder: &[(Cancel, Order)]) -> Vec<Replaced> {
+        let cancels: Vec<Cancel> = ladder.iter().map(|(c, _)| c.clone()).collect();
+        let cancelled = self.send_cancels(&cancels);
+
+        let gone =
+            |r: &Result<(), OrderError>| matches!(r, Ok(()) | Err(OrderError::OrderNotFound(_)));
+        let replacements: Vec<Order> = ladder
+            .iter()
+            .zip(&cancelled)
+            .filter(|(_, r)| gone(r))
+            .map(|((_, o), _)| o.clone())
+            .collect();
+        let mut placed = self.send_orders(&replacements).into_iter();
+
+        cancelled
+            .into_iter()
+            .map(|cancel| Replaced {
+                order: if gone(&cancel) { placed.next() } else { None },
+                cancel,
+            })
+            .collect()
+    }
+
+    /// journals every action up front (one that can't be journaled isn't sent), sends the
+    /// rest concurrently and journals their outcomes
+    pub(crate) fn send_batch<T>(
+        &mut self,
+        actions: &[T],
+        entry: impl Fn(&T) -> JournalAction,
+        order_id: impl Fn(&T::OkType) -> Option<&str>,
+    ) -> Vec<Result<T::OkType, OrderError>>
+    where
+        T: SendWithMngr + Sync,
+        T::OkType: Send,
+    {
+        let seqs: Vec<Result<Option<u64>, OrderError>> = actions
+            .iter()
+            .map(|a| match self.journal.as_mut() {
+                Some(j) => j.sent(entry(a)).map(Some),
+                None => Ok(None),
+            })
+            .collect();
+        let to_send: Vec<&T> = actions
+            .iter()
+            .zip(&seqs)
+            .filter(|(_, s)| s.is_ok())
+            .map(|(a, _)| a)
+            .collect();
+        let mut sent = self.send_concurrently(&to_send).into_iter();
+
+        seqs.into_iter()
+            .map(|seq| {
+                let seq = seq?;
+                let out = sent.next().unwrap();
+                if let (Some(j), Some(seq)) = (self.journal.as_mut(), seq) {
+                    j.outcome(seq, &out, out.as_ref().ok().and_then(&order_id));
+                }
+                out
+            })
+            .collect()
+    }
+
+    /// up to `MAX_IN_FLIGHT` worker threads share the agent's connection pool, results keep
+    /// the order of `actions`
+    fn send_concurrently<T>(&self, actions: &[&T]) -> Vec<Result<T::OkType, OrderError>>
+    where
+        T: SendWithMngr + Sync,
+        T::OkType: Send,
+    {
+        if actions.len() <= 1 {
+            return actions.iter().map(|a| self.send(*a)).collect();
+        }
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Slot<T::OkType>>> =
+            Mutex::new(actions.iter().map(|_| None).collect());
+
+        thread::scope(|s| {
+            for _ in 0..actions.len().min(MAX_IN_FLIGHT) {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(action) = actions.get(i) else {
+                        break;
+                    };
+                    let out = self.send(*action);
+                    results.lock().unwrap()[i] = Some(out);
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.expect("every action is sent by some worker"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::price::Price;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn batches_run_concurrently() {
+        let server = MockServer::start(|req| {
+            thread::sleep(Duration::from_millis(100));
+            match (req.method.as_str(), req.url.as_str()) {
+                ("POST", "/orders") if req.body.contains(r#""size":0"#) => {
+                    (400, r#"{"error": "bad size"}"#.to_string())
+                }
+                ("POST", "/orders") => (200, r#"{"mid": "m"}"#.to_string()),
+                _ => (200, "{}".to_string()),
+            }
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let ords: Vec<Order> = (0..8)
+            .map(|i| Order::new(1, false, Price::from_cents(100 + i), i % 4))
+            .collect();
+        let start = Instant::now();
+        let out = om.send_orders(&ords);
+        assert!(
+            start.elapsed() < Duration::from_millis(500),
+            "{:?}",
+            start.elapsed()
+        );
+
+        let failed: Vec<usize> = (0..8).filter(|i| out[*i].is_err()).collect();
+        assert_eq!(failed, [0, 4]);
+        assert_eq!(om.order_history.len(), 6);
+        assert_eq!(om.order_history[0].1.price, Price::from_cents(101));
+    }
+
+    #[test]
+    fn cancel_replace_never_doubles_a_level() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("DELETE", "/orders/gone") => (404, r#"{"error": "order not found"}"#.to_string()),
+            ("DELETE", "/orders/stuck") => (500, r#"{"error": "try again"}"#.to_string()),
+            ("DELETE", _) => (200, "{}".to_string()),
+            ("POST", "/orders") => (200, r#"{"mid": "new"}"#.to_string()),
+            _ => (404, "{}".to_string()),
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let ladder: Vec<(Cancel, Order)> = ["a", "gone", "stuck"]
+            .iter()
+            .enumerate()
+            .map(|(i, mid)| {
+                (
+                    Cancel::one(mid.to_string(), 1),
+                    Order::new(1, true, Price::from_cents(200 + 25 * i as u64), 1),
+                )
+            })
+            .collect();
+        let out = om.cancel_replace(&ladder);
+
+        assert!(out[0].cancel.is_ok() && out[0].order.as_ref().unwrap().is_ok());
+        assert!(out[1].order.as_ref().unwrap().is_ok());
+        assert!(out[2].cancel.is_err() && out[2].order.is_none());
+        let posts = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "POST")
+            .count();
+        assert_eq!(posts, 2);
+    }
+}
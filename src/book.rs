@@ -0,0 +1,505 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::WebSocketError;
+use crate::price::Price;
+use crate::ws::{ActionReport, ActionStatus, BookTop, RawMsg, SanitizableMsg, WebSocketMsg};
+
+const BOOK_STATES_URL: &str = "https://api.ledgerx.com/trading/book-states";
+
+//
+// SANITIZED BOOKS
+//
+
+/// every contract's book we've loaded a snapshot for, kept current by feeding it
+/// messages from `WebSocketClient::yield_msg`
+#[derive(Debug, Default)]
+pub struct OrderBooks {
+    pub books: HashMap<u64, Book>,
+}
+
+impl OrderBooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// fetches a fresh `/book-states` snapshot for the contract, replacing any book we had
+    pub fn load(&mut self, contract_id: u64) -> Result<&Book, WebSocketError> {
+        let book = RawBookState::fetch(contract_id)?.sanitize();
+        self.books.insert(contract_id, book);
+        Ok(&self.books[&contract_id])
+    }
+    pub fn get(&self, contract_id: u64) -> Option<&Book> {
+        self.books.get(&contract_id)
+    }
+    /// applies a message to the matching book, returns the id of the contract it touched.
+    /// messages for contracts without a loaded snapshot are ignored. a `Resync` reloads the
+    /// contract's snapshot, if that fails the book is left marked `stale`. a `Reconnected`
+    /// does the same for every book and returns `None`.
+    pub fn apply(&mut self, msg: &WebSocketMsg) -> Option<u64> {
+        let contract_id = match msg {
+            WebSocketMsg::Reconnected { .. } => {
+                let ids: Vec<u64> = self.books.keys().copied().collect();
+                for id in ids {
+                    self.books.get_mut(&id)?.stale = true;
+                    let _ = self.load(id);
+                }
+                return None;
+            }
+            WebSocketMsg::Resync { contract_id, .. } => {
+                self.books.get_mut(contract_id)?.stale = true;
+                let _ = self.load(*contract_id);
+                *contract_id
+            }
+            WebSocketMsg::BookTop(bt) => {
+                self.books.get_mut(&bt.contract_id)?.apply_book_top(bt);
+                bt.contract_id
+            }
+            WebSocketMsg::ActionReport(ar) => {
+                self.books.get_mut(&ar.contract_id)?.apply_action_report(ar);
+                ar.contract_id
+            }
+            _ => return None,
+        };
+        Some(contract_id)
+    }
+}
+
+/// an individual resting order (L3)
+#[derive(Debug, Clone)]
+pub struct BookOrder {
+    /// empty when the order was inferred from a `BookTop` rather than a snapshot
+    pub mid: String,
+    pub price: Price,
+    pub size: u64,
+    pub is_ask: bool,
+    pub clock: u64,
+}
+
+/// aggregated size at a price (L2)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub price: Price,
+    pub size: u64,
+    pub orders: usize,
+}
+
+/// full-depth book for a single contract.
+///
+/// the public feed only carries level 1, so depth below the top is exactly as fresh as the
+/// last snapshot plus our own action reports. `clock` is checked on every message and
+/// anything behind the book's clock is dropped. the clock also moves on for changes below the
+/// top and other accounts' orders, none of which are sent, so a jump of more than one is
+/// normal. `stale` is only set when a reload was asked for and failed.
+#[derive(Debug, Clone)]
+pub struct Book {
+    pub contract_id: u64,
+    pub clock: u64,
+    pub stale: bool,
+
+    // orders within a level are in queue order
+    bids: BTreeMap<Price, Vec<BookOrder>>,
+    asks: BTreeMap<Price, Vec<BookOrder>>,
+}
+
+impl Book {
+    pub fn new(contract_id: u64, clock: u64) -> Self {
+        Book {
+            contract_id,
+            clock,
+            stale: false,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    /// bid levels, best (highest) first
+    pub fn bids(&self) -> Vec<Level> {
+        self.bids.values().rev().map(|v| Self::level(v)).collect()
+    }
+    /// ask levels, best (lowest) first
+    pub fn asks(&self) -> Vec<Level> {
+        self.asks.values().map(|v| Self::level(v)).collect()
+    }
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids.values().next_back().map(|v| Self::level(v))
+    }
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks.values().next().map(|v| Self::level(v))
+    }
+    /// every resting order at a price on one side, in queue order
+    pub fn orders_at(&self, is_ask: bool, price: Price) -> &[BookOrder] {
+        self.side(is_ask)
+            .get(&price)
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+    /// `(size, orders)` queued ahead of the given order at its price level
+    pub fn queue_position(&self, mid: &str) -> Option<(u64, usize)> {
+        for level in self.bids.values().chain(self.asks.values()) {
+            if let Some(i) = level.iter().position(|o| o.mid == mid) {
+                return Some((level[..i].iter().map(|o| o.size).sum(), i));
+            }
+        }
+        None
+    }
+
+    pub fn insert(&mut self, order: BookOrder) {
+        self.side_mut(order.is_ask)
+            .entry(order.price)
+            .or_default()
+            .push(order);
+    }
+    /// removes an order by mid wherever it rests, returning it
+    pub fn remove(&mut self, mid: &str) -> Option<BookOrder> {
+        for side in [&mut self.bids, &mut self.asks] {
+            let found = side
+                .iter()
+                .find_map(|(p, v)| v.iter().position(|o| o.mid == mid).map(|i| (*p, i)));
+            if let Some((price, i)) = found {
+                let level = side.get_mut(&price).unwrap();
+                let out = level.remove(i);
+                if level.is_empty() {
+                    side.remove(&price);
+                }
+                return Some(out);
+            }
+        }
+        None
+    }
+
+    /// reconciles level 1 against a `BookTop`. levels better than the new top are dropped,
+    /// and a size change at the top is taken from the front of the queue (fills/cancels) or
+    /// added to its back (new orders) as an anonymous order.
+    pub fn apply_book_top(&mut self, bt: &BookTop) {
+        if !self.advance_clock(bt.clock) {
+            return;
+        }
+        self.reconcile_top(false, bt.bid, bt.bid_size, bt.clock);
+        self.reconcile_top(true, bt.ask, bt.ask_size, bt.clock);
+    }
+
+    /// tracks one of our own orders through its lifecycle
+    pub fn apply_action_report(&mut self, ar: &ActionReport) {
+        if !self.advance_clock(ar.clock) {
+            return;
+        }
+        match ar.status {
+            ActionStatus::Inserted | ActionStatus::Edited => {
+                self.remove(&ar.mid);
+                let ours = BookOrder {
+                    mid: ar.mid.clone(),
+                    price: ar.price,
+                    size: ar.open_size,
+                    is_ask: ar.is_ask,
+                    clock: ar.clock,
+                };
+                // a `BookTop` with the same clock may have got here first and queued the order
+                // anonymously, in which case that entry is ours
+                let level = self.side_mut(ar.is_ask).entry(ar.price).or_default();
+                let anonymous = level.iter().rposition(|o| {
+                    o.mid.is_empty() && o.clock == ar.clock && o.size == ar.open_size
+                });
+                match anonymous {
+                    Some(i) => level[i] = ours,
+                    None => level.push(ours),
+                }
+            }
+            ActionStatus::PartiallyFilled => {
+                let resting = self
+                    .side_mut(ar.is_ask)
+                    .get_mut(&ar.price)
+                    .and_then(|level| level.iter_mut().find(|o| o.mid == ar.mid));
+                match resting {
+                    Some(o) => o.size = ar.open_size,
+                    None => self.insert(BookOrder {
+                        mid: ar.mid.clone(),
+                        price: ar.price,
+                        size: ar.open_size,
+                        is_ask: ar.is_ask,
+                        clock: ar.clock,
+                    }),
+                }
+            }
+            ActionStatus::Filled | ActionStatus::Cancelled | ActionStatus::NotFilled => {
+                self.remove(&ar.mid);
+            }
+            ActionStatus::Rejected(_) | ActionStatus::Other(_) => {}
+        }
+    }
+
+    /// returns false if the message is already reflected in the book
+    fn advance_clock(&mut self, clock: u64) -> bool {
+        if clock < self.clock {
+            return false;
+        }
+        self.clock = clock;
+        true
+    }
+
+    fn reconcile_top(&mut self, is_ask: bool, price: Price, size: u64, clock: u64) {
+        let side = self.side_mut(is_ask);
+
+        // an empty side is reported as a zero price
+        if size == 0 || price == Price::ZERO {
+            side.clear();
+            return;
+        }
+        let better: Vec<Price> = if is_ask {
+            side.range(..price).map(|(p, _)| *p).collect()
+        } else {
+            side.range(price..)
+                .filter(|(p, _)| **p > price)
+                .map(|(p, _)| *p)
+                .collect()
+        };
+        for p in better {
+            side.remove(&p);
+        }
+
+        let level = side.entry(price).or_default();
+        let resting: u64 = level.iter().map(|o| o.size).sum();
+        if size > resting {
+            level.push(BookOrder {
+                mid: String::new(),
+                price,
+                size: size - resting,
+                is_ask,
+                clock,
+            });
+        } else {
+            let mut excess = resting - size;
+            while excess > 0 {
+                let front = &mut level[0];
+                if front.size > excess {
+                    front.size -= excess;
+                    excess = 0;
+                } else {
+                    excess -= front.size;
+                    level.remove(0);
+                }
+            }
+        }
+    }
+
+    fn side(&self, is_ask: bool) -> &BTreeMap<Price, Vec<BookOrder>> {
+        if is_ask {
+            &self.asks
+        } else {
+            &self.bids
+        }
+    }
+    fn side_mut(&mut self, is_ask: bool) -> &mut BTreeMap<Price, Vec<BookOrder>> {
+        if is_ask {
+            &mut self.asks
+        } else {
+            &mut self.bids
+        }
+    }
+    fn level(orders: &[BookOrder]) -> Level {
+        Level {
+            price: orders[0].price,
+            size: orders.iter().map(|o| o.size).sum(),
+            orders: orders.len(),
+        }
+    }
+}
+
+//
+// NON-SANITIZED BOOKS
+//
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawBookState {
+    data: RawBookStateData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawBookStateData {
+    contract_id: u64,
+    clock: u64,
+    book_states: Vec<RawBookEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawBookEntry {
+    mid: String,
+    is_ask: bool,
+    price: u64,
+    size: u64,
+    clock: u64,
+}
+
+impl RawBookState {
+    pub fn fetch(contract_id: u64) -> Result<Self, WebSocketError> {
+        let resp = ureq::get(&format!("{}/{}", BOOK_STATES_URL, contract_id))
+            .set("Accept", "application/json")
+            .call()
+            .map_err(|e| WebSocketError::ConnectionError(e.to_string()))?;
+        let resp_string = resp
+            .into_string()
+            .map_err(|e| WebSocketError::ConnectionError(e.to_string()))?;
+        RawBookState::parse(&resp_string)
+    }
+}
+
+impl<'a> SanitizableMsg<'a> for RawBookState {
+    type OUT = Book;
+    fn sanitize(self) -> Self::OUT {
+        let mut out = Book::new(self.data.contract_id, self.data.clock);
+
+        let mut entries = self.data.book_states;
+        // snapshot entries aren't guaranteed to be in queue order
+        entries.sort_by_key(|e| e.clock);
+        for e in entries {
+            out.insert(BookOrder {
+                mid: e.mid,
+                price: Price::from_cents(e.price),
+                size: e.size,
+                is_ask: e.is_ask,
+                clock: e.clock,
+            });
+        }
+
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"{"data": {"contract_id": 22252392, "clock": 100, "book_states": [
+        {"mid": "b2", "is_ask": false, "price": 150, "size": 5, "clock": 90},
+        {"mid": "b1", "is_ask": false, "price": 150, "size": 2, "clock": 80},
+        {"mid": "b3", "is_ask": false, "price": 125, "size": 10, "clock": 70},
+        {"mid": "a1", "is_ask": true, "price": 175, "size": 3, "clock": 85},
+        {"mid": "a2", "is_ask": true, "price": 200, "size": 1, "clock": 60}
+    ]}}"#;
+
+    fn book() -> Book {
+        RawBookState::parse(SNAPSHOT).unwrap().sanitize()
+    }
+
+    fn top(bid: u64, bid_size: u64, ask: u64, ask_size: u64, clock: u64) -> BookTop {
+        BookTop {
+            bid: Price::from_cents(bid),
+            bid_size,
+            ask: Price::from_cents(ask),
+            ask_size,
+            contract_id: 22252392,
+            contract_type: 0,
+            clock,
+        }
+    }
+
+    #[test]
+    fn snapshot_levels() {
+        let b = book();
+        assert_eq!(b.clock, 100);
+        assert_eq!(
+            b.bids(),
+            vec![
+                Level {
+                    price: Price::from_cents(150),
+                    size: 7,
+                    orders: 2
+                },
+                Level {
+                    price: Price::from_cents(125),
+                    size: 10,
+                    orders: 1
+                },
+            ]
+        );
+        assert_eq!(b.best_ask().unwrap().price, Price::from_cents(175));
+        assert_eq!(b.queue_position("b2"), Some((2, 1)));
+        assert_eq!(b.queue_position("a2"), Some((0, 0)));
+    }
+
+    #[test]
+    fn book_top_updates_level_one() {
+        let mut b = book();
+
+        // stale message, already in the snapshot
+        b.apply_book_top(&top(900, 1, 950, 1, 99));
+        assert_eq!(b.best_bid().unwrap().price, Price::from_cents(150));
+
+        // b1 partially traded away at the front of the queue
+        b.apply_book_top(&top(150, 6, 175, 3, 101));
+        assert_eq!(b.orders_at(false, Price::from_cents(150))[0].size, 1);
+        assert!(!b.stale);
+
+        // ask level lifted, next level is the new top. the clock skips the unseen changes
+        // below the top, which doesn't make the book stale.
+        b.apply_book_top(&top(150, 6, 200, 4, 105));
+        assert_eq!(
+            b.asks(),
+            vec![Level {
+                price: Price::from_cents(200),
+                size: 4,
+                orders: 2
+            }]
+        );
+        assert!(!b.stale);
+        assert_eq!(b.clock, 105);
+    }
+
+    #[test]
+    fn action_reports_track_our_orders() {
+        let mut b = book();
+        let mut ar = ActionReport {
+            mid: "ours".to_string(),
+            contract_id: 22252392,
+            status: ActionStatus::Inserted,
+            status_reason: 0,
+            is_ask: false,
+            price: Price::from_cents(150),
+            size: 4,
+            filled_price: Price::from_cents(0),
+            filled_size: 0,
+            open_size: 4,
+            clock: 101,
+            client_tag: None,
+        };
+        b.apply_action_report(&ar);
+        assert_eq!(b.queue_position("ours"), Some((7, 2)));
+
+        ar.status = ActionStatus::PartiallyFilled;
+        ar.open_size = 1;
+        ar.clock = 102;
+        b.apply_action_report(&ar);
+        assert_eq!(b.best_bid().unwrap().size, 8);
+
+        ar.status = ActionStatus::Cancelled;
+        ar.clock = 103;
+        b.apply_action_report(&ar);
+        assert_eq!(b.queue_position("ours"), None);
+    }
+
+    #[test]
+    fn book_top_before_our_insert() {
+        let mut b = book();
+        let ar = ActionReport {
+            mid: "ours".to_string(),
+            contract_id: 22252392,
+            status: ActionStatus::Inserted,
+            status_reason: 0,
+            is_ask: false,
+            price: Price::from_cents(150),
+            size: 4,
+            filled_price: Price::from_cents(0),
+            filled_size: 0,
+            open_size: 4,
+            clock: 101,
+            client_tag: None,
+        };
+        // the public feed shows our order before its report arrives
+        b.apply_book_top(&top(150, 11, 175, 3, 101));
+        b.apply_action_report(&ar);
+
+        let bid = b.best_bid().unwrap();
+        assert_eq!((bid.size, bid.orders), (11, 3));
+        assert_eq!(b.queue_position("ours"), Some((7, 2)));
+    }
+}
@@ -1,10 +1,19 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use websocket::url::ParseError;
 
+use crate::price::Price;
+
 #[derive(Debug)]
 pub enum WebSocketError {
     ConnectionError(String),
     MsgParsing(String, usize, usize),
-    UnknownMsgType(String),
+    AuthFailure(String),
+    ReconnectExhausted(String, u64),
+    UnexpectedBinary(Vec<u8>),
+    Closed,
     Misc(String),
 }
 
@@ -18,9 +27,204 @@ impl From<websocket::WebSocketError> for WebSocketError {
         WebSocketError::ConnectionError(format!("connecting failed!\n{}", f))
     }
 }
+#[cfg(feature = "tokio")]
+impl From<tokio_tungstenite::tungstenite::Error> for WebSocketError {
+    fn from(f: tokio_tungstenite::tungstenite::Error) -> Self {
+        WebSocketError::ConnectionError(f.to_string())
+    }
+}
 
+#[derive(Debug)]
 pub enum TableError {
+    /// the exchange answered with a non-2xx status
     ClientError(u16, String),
+    /// the request failed before a response came back
+    Transport(String),
+    MalformedResponse(String),
+}
+
+impl From<ureq::Error> for TableError {
+    fn from(f: ureq::Error) -> Self {
+        match f {
+            ureq::Error::Status(status, resp) => {
+                TableError::ClientError(status, resp.into_string().unwrap_or_default())
+            }
+            ureq::Error::Transport(t) => TableError::Transport(t.to_string()),
+        }
+    }
+}
+impl From<std::io::Error> for TableError {
+    fn from(f: std::io::Error) -> Self {
+        TableError::MalformedResponse(f.to_string())
+    }
+}
+impl From<serde_json::Error> for TableError {
+    fn from(f: serde_json::Error) -> Self {
+        TableError::MalformedResponse(f.to_string())
+    }
 }
 
-pub enum OrderError {}
+/// why `OrderBuilder` refused to build an order. nothing has been sent when this comes back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderValidationError {
+    /// no contract with this id in the spec table
+    UnknownContract(u64),
+    /// the contract exists but isn't trading
+    Inactive(u64),
+    Expired {
+        contract_id: u64,
+        date_expires: DateTime<Utc>,
+    },
+    /// a limit order was built without a price
+    MissingPrice,
+    /// `price` isn't a multiple of the contract's `min_increment`
+    InvalidIncrement {
+        price: Price,
+        min_increment: Price,
+    },
+    ZeroSize,
+    /// the contract is only open to eligible contract participants
+    EcpOnly(u64),
+}
+
+/// everything that can go wrong sending an order, edit, cancel or query to the exchange
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    /// not enough collateral to cover the order
+    InsufficientCollateral(String),
+    /// the price isn't a multiple of the contract's `min_increment`
+    InvalidPriceIncrement(String),
+    UnknownContract(String),
+    /// the order id doesn't exist or is no longer open
+    OrderNotFound(String),
+    /// the exchange throttled us, `retry_after` is its hint (if any) on when to try again
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// our own `RateLimiter` had no token to spare and is set to `OnExhausted::Reject`.
+    /// nothing was sent, `retry_after` is when the next token is due.
+    Throttled {
+        retry_after: Duration,
+    },
+    /// the api key is missing, invalid or not allowed to do this
+    AuthFailure(String),
+    /// the request never reached the exchange (dns, connection refused, bad url)
+    ConnectionFailed(String),
+    /// the request was sent, but the connection failed before a response came back. the
+    /// action may or may not have happened.
+    Transport(String),
+    /// the exchange answered with something we couldn't make sense of
+    MalformedResponse(String),
+    /// the journal couldn't be written, so nothing was sent
+    Journal(String),
+    /// any other error the exchange reported
+    Exchange {
+        status: u16,
+        message: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawErrorBody {
+    Nested { error: RawErrorDetail },
+    Flat { error: String },
+    Message { message: String },
+}
+
+#[derive(Deserialize)]
+struct RawErrorDetail {
+    message: String,
+}
+
+impl OrderError {
+    /// classifies a non-2xx response from the exchange by status code, then by error message.
+    /// a bare 404 is left as `Exchange`, only the caller knows whether it was an order that's
+    /// missing, see `on_order`.
+    /// `retry_after` is the raw `Retry-After` header, in seconds.
+    pub fn from_response(status: u16, retry_after: Option<&str>, body: &str) -> Self {
+        let message = match serde_json::from_str::<RawErrorBody>(body) {
+            Ok(RawErrorBody::Nested { error }) => error.message,
+            Ok(RawErrorBody::Flat { error }) => error,
+            Ok(RawErrorBody::Message { message }) => message,
+            Err(_) => body.trim().to_string(),
+        };
+        let lower = message.to_lowercase();
+
+        match status {
+            401 | 403 => OrderError::AuthFailure(message),
+            429 => OrderError::RateLimited {
+                retry_after: retry_after
+                    .and_then(|s| s.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs),
+            },
+            _ if lower.contains("insufficient collateral") => {
+                OrderError::InsufficientCollateral(message)
+            }
+            _ if lower.contains("min_increment") || lower.contains("price increment") => {
+                OrderError::InvalidPriceIncrement(message)
+            }
+            _ if lower.contains("unknown contract") => OrderError::UnknownContract(message),
+            _ if lower.contains("order") && lower.contains("not found") => {
+                OrderError::OrderNotFound(message)
+            }
+            _ => OrderError::Exchange { status, message },
+        }
+    }
+
+    /// for requests addressing a single order (`/orders/{mid}`): a 404 there means the order
+    /// doesn't exist or is no longer open
+    pub(crate) fn on_order(self) -> Self {
+        match self {
+            OrderError::Exchange {
+                status: 404,
+                message,
+            } => OrderError::OrderNotFound(message),
+            e => e,
+        }
+    }
+}
+
+impl From<ureq::Error> for OrderError {
+    fn from(f: ureq::Error) -> Self {
+        match f {
+            ureq::Error::Status(status, resp) => {
+                let retry_after = resp.header("retry-after").map(|s| s.to_string());
+                let body = resp.into_string().unwrap_or_default();
+                OrderError::from_response(status, retry_after.as_deref(), &body)
+            }
+            ureq::Error::Transport(t) => match t.kind() {
+                ureq::ErrorKind::InvalidUrl
+                | ureq::ErrorKind::UnknownScheme
+                | ureq::ErrorKind::Dns
+                | ureq::ErrorKind::ConnectionFailed
+                | ureq::ErrorKind::InsecureRequestHttpsOnly => {
+                    OrderError::ConnectionFailed(t.to_string())
+                }
+                _ => OrderError::Transport(t.to_string()),
+            },
+        }
+    }
+}
+impl From<std::io::Error> for OrderError {
+    fn from(f: std::io::Error) -> Self {
+        OrderError::MalformedResponse(f.to_string())
+    }
+}
+impl From<serde_json::Error> for OrderError {
+    fn from(f: serde_json::Error) -> Self {
+        OrderError::MalformedResponse(f.to_string())
+    }
+}
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for OrderError {
+    fn from(f: reqwest::Error) -> Self {
+        if f.is_builder() || f.is_connect() {
+            OrderError::ConnectionFailed(f.to_string())
+        } else if f.is_decode() {
+            OrderError::MalformedResponse(f.to_string())
+        } else {
+            OrderError::Transport(f.to_string())
+        }
+    }
+}
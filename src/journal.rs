@@ -0,0 +1,295 @@
+//! append-only JSON lines journal of everything `OrderMngr` sends and what came back
+//!
+//! every action is written before it goes on the wire and its outcome once the exchange has
+//! answered, tied together by `seq`. an action with no outcome was in flight when the process
+//! died, so the exchange may or may not have it. check with `OrderMngr::order_status` or
+//! `open_orders`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::OrderError;
+use crate::order::{Cancel, Order, OrderEdit, OrderMngr, OrderResponse};
+use crate::tracker::OrderTracker;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// about to be sent
+    Sent { action: JournalAction },
+    /// the exchange accepted action `seq`, `order_id` is set for new orders
+    Accepted {
+        request: u64,
+        order_id: Option<String>,
+    },
+    /// action `seq` failed, `error` is the `OrderError` in debug form
+    Failed { request: u64, error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "body", rename_all = "snake_case")]
+pub enum JournalAction {
+    Order(Order),
+    Edit(OrderEdit),
+    Cancel(Cancel),
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    next_seq: u64,
+}
+
+impl Journal {
+    /// opens (or creates) the journal at `path` for appending, numbering on from whatever is
+    /// already in it
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let next_seq = match Journal::load(&path) {
+            Ok(entries) => entries.last().map_or(0, |e| e.seq + 1),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // drop a torn last line, or the next entry would be glued onto it
+        let contents = std::fs::read(&path)?;
+        if contents.last().is_some_and(|b| *b != b'\n') {
+            let keep = contents
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |i| i + 1);
+            file.set_len(keep as u64)?;
+        }
+        Ok(Journal {
+            path,
+            file,
+            next_seq,
+        })
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// every entry in the journal at `path`, oldest first. a torn last line (the process
+    /// died mid-write) is skipped, a bad line anywhere else is an `InvalidData` error.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<JournalEntry>> {
+        let lines: Vec<String> = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<io::Result<_>>()?;
+        let last = lines.len().saturating_sub(1);
+
+        let mut out = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => out.push(entry),
+                Err(_) if i == last => {}
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("journal line {}: {}", i + 1, e),
+                    ))
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// actions that were sent but never got an outcome
+    pub fn unresolved(entries: &[JournalEntry]) -> Vec<&JournalEntry> {
+        let resolved: HashSet<u64> = entries
+            .iter()
+            .filter_map(|e| match e.event {
+                JournalEvent::Accepted { request, .. } | JournalEvent::Failed { request, .. } => {
+                    Some(request)
+                }
+                _ => None,
+            })
+            .collect();
+        entries
+            .iter()
+            .filter(|e| matches!(e.event, JournalEvent::Sent { .. }) && !resolved.contains(&e.seq))
+            .collect()
+    }
+
+    fn write(&mut self, event: JournalEvent) -> io::Result<u64> {
+        let entry = JournalEntry {
+            seq: self.next_seq,
+            timestamp: Utc::now(),
+            event,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        // one write per line, so a crash can only ever tear the last one
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.next_seq += 1;
+        Ok(entry.seq)
+    }
+    pub(crate) fn sent(&mut self, action: JournalAction) -> Result<u64, OrderError> {
+        self.write(JournalEvent::Sent { action })
+            .map_err(|e| OrderError::Journal(e.to_string()))
+    }
+    /// records the outcome of `request`. the exchange has already answered by now, so a
+    /// failed write doesn't hide its answer: the action is left unresolved instead.
+    pub(crate) fn outcome<T>(
+        &mut self,
+        request: u64,
+        out: &Result<T, OrderError>,
+        order_id: Option<&str>,
+    ) {
+        let event = match out {
+            Ok(_) => JournalEvent::Accepted {
+                request,
+                order_id: order_id.map(|s| s.to_string()),
+            },
+            Err(e) => JournalEvent::Failed {
+                request,
+                error: format!("{:?}", e),
+            },
+        };
+        let _ = self.write(event);
+    }
+}
+
+/// rebuilds `OrderMngr::order_history` from a journal: every order the exchange accepted,
+/// as it was sent. each one is tracked in `orders` too, along with the edits and cancels the
+/// exchange accepted after it.
+pub fn replay(entries: &[JournalEntry], orders: &mut OrderTracker) -> Vec<(OrderResponse, Order)> {
+    let sent: HashMap<u64, &JournalAction> = entries
+        .iter()
+        .filter_map(|e| match &e.event {
+            JournalEvent::Sent { action } => Some((e.seq, action)),
+            _ => None,
+        })
+        .collect();
+
+    let mut out = Vec::new();
+    for e in entries {
+        let JournalEvent::Accepted { request, order_id } = &e.event else {
+            continue;
+        };
+        match (sent.get(request), order_id) {
+            (Some(JournalAction::Order(ord)), Some(order_id)) => {
+                let resp = OrderResponse {
+                    order_id: order_id.clone(),
+                };
+                orders.track(&resp, ord);
+                out.push((resp, ord.clone()));
+            }
+            (Some(JournalAction::Edit(edit)), _) => orders.edited(edit),
+            (Some(JournalAction::Cancel(cancel)), _) => orders.cancelled(cancel),
+            _ => {}
+        }
+    }
+    return out;
+}
+
+impl<'a> OrderMngr<'a> {
+    /// opens the journal at `path`, reloads `order_history` (and starts tracking those orders
+    /// again, as edited or cancelled since) from it and journals everything sent from here
+    /// on. `reconcile` afterwards to learn what happened to them while we were down.
+    pub fn open_journal<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Vec<JournalEntry>> {
+        let entries = match Journal::load(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        self.order_history = replay(&entries, &mut self.orders);
+        self.journal = Some(Journal::open(path)?);
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::price::Price;
+    use crate::ws::ActionStatus;
+
+    fn tmp(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn journal_survives_a_restart() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") if req.body.contains(r#""size":3"#) => {
+                (200, r#"{"mid": "m2"}"#.to_string())
+            }
+            ("POST", "/orders") => (200, r#"{"mid": "m1"}"#.to_string()),
+            ("DELETE", "/orders/m1") => (404, r#"{"error": "order not found"}"#.to_string()),
+            _ => (200, "{}".to_string()),
+        });
+        let path = tmp("journal_survives_a_restart");
+
+        let ord = Order::new(1, false, Price::from_cents(175), 2);
+        {
+            let mut om = OrderMngr::new(&server.url, "key");
+            om.open_journal(&path).unwrap();
+            om.send_order(&ord).unwrap();
+            om.send_edit(&OrderEdit::new(
+                "m1".to_string(),
+                1,
+                Price::from_cents(150),
+                2,
+            ))
+            .unwrap();
+            assert!(om.send_cancel(&Cancel::one("m1".to_string(), 1)).is_err());
+            om.send_order(&Order::new(1, true, Price::from_cents(300), 3))
+                .unwrap();
+            om.send_cancel(&Cancel::one("m2".to_string(), 1)).unwrap();
+        }
+        // a crash mid-write leaves a torn line behind
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(br#"{"seq": 10, "timestamp": "#).unwrap();
+
+        let mut om = OrderMngr::new(&server.url, "key");
+        let entries = om.open_journal(&path).unwrap();
+        assert_eq!(entries.len(), 10);
+        assert!(Journal::unresolved(&entries).is_empty());
+        assert!(matches!(
+            &entries[5].event,
+            JournalEvent::Failed { request: 4, error } if error.contains("OrderNotFound")
+        ));
+        assert_eq!(om.order_history.len(), 2);
+        assert_eq!(om.order_history[0].0.order_id, "m1");
+        assert_eq!(om.order_history[0].1, ord);
+
+        // the accepted edit and cancel are replayed too
+        let m1 = om.orders.get("m1").unwrap();
+        assert_eq!(
+            (m1.price, m1.status),
+            (Price::from_cents(150), ActionStatus::Edited)
+        );
+        assert!(m1.is_open());
+        let m2 = om.orders.get("m2").unwrap();
+        assert_eq!(m2.status, ActionStatus::Cancelled);
+        assert!(!m2.is_open());
+
+        // numbering carries on after a reload
+        om.send_order(&ord).unwrap();
+        let entries = Journal::load(&path).unwrap();
+        assert_eq!(entries.last().unwrap().seq, 11);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,22 @@
+#![allow(clippy::needless_return)]
+
+pub mod account;
+pub mod batch;
+pub mod book;
 pub mod error;
+pub mod journal;
+pub mod limit;
 pub mod order;
+#[cfg(feature = "reqwest")]
+pub mod order_async;
+pub mod page;
+pub mod price;
+pub mod retry;
 pub mod table;
+pub mod tracker;
 pub mod ws;
+#[cfg(feature = "tokio")]
+pub mod ws_async;
+
+#[cfg(test)]
+mod mock;
@@ -0,0 +1,333 @@
+//! client-side token buckets pacing what `OrderMngr` sends, one each for orders (and edits),
+//! cancels and reads
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::OrderError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// new orders and edits
+    Order,
+    Cancel,
+    /// queries: open orders, statuses, history, positions, ...
+    Read,
+}
+
+impl RequestKind {
+    fn index(self) -> usize {
+        match self {
+            RequestKind::Order => 0,
+            RequestKind::Cancel => 1,
+            RequestKind::Read => 2,
+        }
+    }
+}
+
+/// `burst` requests at once, refilling at `per_second`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+/// what to do when a bucket is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExhausted {
+    /// wait for the next token
+    Block,
+    /// fail straight away with `OrderError::Throttled`, which is never retried
+    Reject,
+}
+
+/// budget usage of one bucket since the limiter was made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketMetrics {
+    pub capacity: u32,
+    /// tokens left right now
+    pub available: f64,
+    pub granted: u64,
+    pub rejected: u64,
+    /// total time spent blocked waiting for tokens
+    pub waited: Duration,
+    /// 429s the exchange sent despite the limiter, a sign the limits are set too high
+    pub throttled: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last: Instant,
+    metrics: BucketMetrics,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Bucket {
+            limit,
+            tokens: limit.burst as f64,
+            last: Instant::now(),
+            metrics: BucketMetrics {
+                capacity: limit.burst,
+                available: limit.burst as f64,
+                granted: 0,
+                rejected: 0,
+                waited: Duration::ZERO,
+                throttled: 0,
+            },
+        }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        self.last = now;
+    }
+    /// takes a token, or says how long until one is there
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.metrics.granted += 1;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.limit.per_second,
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<[Bucket; 3]>,
+    on_exhausted: OnExhausted,
+}
+
+impl RateLimiter {
+    /// panics if a limit has a `burst` of 0 or a `per_second` that isn't positive, such a
+    /// bucket never has a token to give
+    pub fn new(
+        orders: RateLimit,
+        cancels: RateLimit,
+        reads: RateLimit,
+        on_exhausted: OnExhausted,
+    ) -> Self {
+        for (name, l) in [("orders", orders), ("cancels", cancels), ("reads", reads)] {
+            assert!(
+                l.burst >= 1 && l.per_second > 0.0 && l.per_second.is_finite(),
+                "rate limit for {} needs a burst of at least 1 and a positive per_second, got {:?}",
+                name,
+                l
+            );
+        }
+        RateLimiter {
+            buckets: Mutex::new([
+                Bucket::new(orders),
+                Bucket::new(cancels),
+                Bucket::new(reads),
+            ]),
+            on_exhausted,
+        }
+    }
+
+    /// takes a token for `kind`, blocking or failing as configured when there's none
+    pub fn acquire(&self, kind: RequestKind) -> Result<(), OrderError> {
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let bucket = &mut buckets[kind.index()];
+                match bucket.take(Instant::now()) {
+                    Ok(()) => {
+                        bucket.metrics.waited += waited;
+                        return Ok(());
+                    }
+                    Err(wait) if self.on_exhausted == OnExhausted::Reject => {
+                        bucket.metrics.rejected += 1;
+                        return Err(OrderError::Throttled { retry_after: wait });
+                    }
+                    Err(wait) => wait,
+                }
+            };
+            // another thread may beat us to the token, in which case we go round again
+            thread::sleep(wait);
+            waited += wait;
+        }
+    }
+
+    /// the exchange throttled us anyway: empty the bucket so nothing more goes out until it's
+    /// had `retry_after` (or one token's worth of time) to refill
+    pub fn throttled(&self, kind: RequestKind, retry_after: Option<Duration>) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[kind.index()];
+        let now = Instant::now();
+        bucket.refill(now);
+        bucket.tokens = match retry_after {
+            Some(d) => -(d.as_secs_f64() * bucket.limit.per_second),
+            None => 0.0,
+        };
+        bucket.metrics.throttled += 1;
+    }
+
+    pub fn metrics(&self, kind: RequestKind) -> BucketMetrics {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[kind.index()];
+        bucket.refill(Instant::now());
+        BucketMetrics {
+            available: bucket.tokens.max(0.0),
+            ..bucket.metrics
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::order::{Cancel, Order, OrderMngr};
+    use crate::price::Price;
+    use crate::retry::RetryPolicy;
+
+    fn limit(burst: u32, per_second: f64) -> RateLimit {
+        RateLimit { burst, per_second }
+    }
+
+    #[test]
+    fn buckets_are_separate() {
+        let l = RateLimiter::new(
+            limit(2, 1.0),
+            limit(1, 1.0),
+            limit(5, 1.0),
+            OnExhausted::Reject,
+        );
+        assert!(l.acquire(RequestKind::Order).is_ok());
+        assert!(l.acquire(RequestKind::Order).is_ok());
+        match l.acquire(RequestKind::Order) {
+            Err(OrderError::Throttled { retry_after: d }) => {
+                assert!(d > Duration::from_millis(900) && d <= Duration::from_secs(1))
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+        // cancels still go out while orders are exhausted
+        assert!(l.acquire(RequestKind::Cancel).is_ok());
+
+        let m = l.metrics(RequestKind::Order);
+        assert_eq!((m.granted, m.rejected, m.capacity), (2, 1, 2));
+        assert!(m.available < 0.1);
+        assert_eq!(l.metrics(RequestKind::Read).available, 5.0);
+    }
+
+    #[test]
+    fn blocking_waits_for_a_token() {
+        let l = RateLimiter::new(
+            limit(1, 20.0),
+            limit(1, 1.0),
+            limit(1, 1.0),
+            OnExhausted::Block,
+        );
+        let start = Instant::now();
+        for _ in 0..3 {
+            l.acquire(RequestKind::Order).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert!(l.metrics(RequestKind::Order).waited >= Duration::from_millis(90));
+
+        l.throttled(RequestKind::Cancel, Some(Duration::from_secs(30)));
+        assert_eq!(l.metrics(RequestKind::Cancel).available, 0.0);
+        assert_eq!(l.metrics(RequestKind::Cancel).throttled, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "burst of at least 1")]
+    fn empty_buckets_are_refused() {
+        RateLimiter::new(
+            limit(1, 1.0),
+            limit(0, 1.0),
+            limit(1, 1.0),
+            OnExhausted::Block,
+        );
+    }
+
+    #[test]
+    fn rejects_are_not_retried() {
+        let server = MockServer::start(|_| (200, r#"{"mid": "m"}"#.to_string()));
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }));
+        om.rate_limit(RateLimiter::new(
+            limit(1, 0.5),
+            limit(1, 1.0),
+            limit(1, 1.0),
+            OnExhausted::Reject,
+        ));
+
+        let ord = Order::new(1, false, Price::from_cents(100), 1);
+        assert!(om.send_order(&ord).is_ok());
+        assert!(matches!(
+            om.send_order(&ord),
+            Err(OrderError::Throttled { .. })
+        ));
+        assert_eq!(om.limiter().unwrap().metrics(RequestKind::Order).rejected, 1);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn mngr_is_paced() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "POST" => (200, r#"{"mid": "m"}"#.to_string()),
+            _ => (429, r#"{"error": "slow down"}"#.to_string()),
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.rate_limit(RateLimiter::new(
+            limit(1, 0.5),
+            limit(5, 1.0),
+            limit(5, 1.0),
+            OnExhausted::Reject,
+        ));
+
+        let ord = Order::new(1, false, Price::from_cents(100), 1);
+        assert!(om.send_order(&ord).is_ok());
+        assert!(matches!(
+            om.send_order(&ord),
+            Err(OrderError::Throttled { .. })
+        ));
+        assert_eq!(server.requests().len(), 1);
+
+        // a 429 that slips through drains the bucket
+        assert!(om.send_cancel(&Cancel::all()).is_err());
+        let m = om.limiter().unwrap().metrics(RequestKind::Cancel);
+        assert_eq!((m.granted, m.throttled), (1, 1));
+        assert!(matches!(
+            om.send_cancel(&Cancel::all()),
+            Err(OrderError::Throttled { .. })
+        ));
+        assert_eq!(server.requests().len(), 2);
+
+        // so do 429s on account reads and fill pages
+        assert!(om.collateral_balances().is_err());
+        assert_eq!(
+            om.limiter().unwrap().metrics(RequestKind::Read).throttled,
+            1
+        );
+        assert_eq!(server.requests().len(), 3);
+        om.rate_limit(RateLimiter::new(
+            limit(5, 1.0),
+            limit(5, 1.0),
+            limit(5, 1.0),
+            OnExhausted::Reject,
+        ));
+        assert!(matches!(
+            om.fills().next(),
+            Some(Err(OrderError::RateLimited { .. }))
+        ));
+        assert_eq!(
+            om.limiter().unwrap().metrics(RequestKind::Read).throttled,
+            1
+        );
+        assert_eq!(server.requests().len(), 4);
+    }
+}
@@ -10,17 +10,14 @@ pub fn main() {
 
     for _ in 0..25 {
         let msg = c.yield_msg().unwrap();
-        match msg {
-            WebSocketMsg::BookTop(bt) => {
-                let contract = ct.id_table[&bt.contract_id].as_ref();
-                if let ContractSpec::Option(opt) = contract {
-                    println!(
-                        "{}: {} @ {}, {}x{}",
-                        opt.label, bt.bid, bt.ask, bt.bid_size, bt.ask_size
-                    );
-                }
+        if let WebSocketMsg::BookTop(bt) = msg {
+            let contract = ct.id_table[&bt.contract_id].as_ref();
+            if let ContractSpec::Option(opt) = contract {
+                println!(
+                    "{}: {} @ {}, {}x{}",
+                    opt.label, bt.bid, bt.ask, bt.bid_size, bt.ask_size
+                );
             }
-            _ => {}
-        };
+        }
     }
 }
@@ -0,0 +1,149 @@
+//! local HTTP endpoint for exercising the REST paths in tests
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::table::{ContractSpecTable, RawContractSpecTable};
+use crate::ws::{RawMsg, SanitizableMsg};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Recorded {
+    pub method: String,
+    /// path and query, e.g. `/orders/abc?limit=5`
+    pub url: String,
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+pub(crate) struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// answers every request with `handler`'s `(status, body)`, each connection on its own
+    /// thread so concurrent clients really are served concurrently. every response closes its
+    /// connection, an idle keep-alive connection can't hold up the others.
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Recorded) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler = Arc::new(handler);
+
+        let handle = {
+            let requests = requests.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        let Some(rec) = read_request(&stream) else {
+                            return;
+                        };
+                        let (status, out) = handler(&rec);
+                        requests.lock().unwrap().push(rec);
+                        let _ = write!(
+                            &stream,
+                            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                            status,
+                            out.len(),
+                            out
+                        );
+                    });
+                }
+            })
+        };
+
+        MockServer {
+            url,
+            requests,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+    /// every request answered so far, in the order they were answered
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wakes the accept loop up so it sees `stopped`
+        let _ = TcpStream::connect(self.url.trim_start_matches("http://"));
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+/// reads one request off `stream`, `None` if it isn't well formed HTTP/1.1
+fn read_request(stream: &TcpStream) -> Option<Recorded> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let url = parts.next()?.to_string();
+
+    let (mut length, mut authorization) = (0, None);
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().ok()?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Recorded {
+        method,
+        url,
+        authorization,
+        body: String::from_utf8(body).ok()?,
+    })
+}
+
+/// four CBTC options on a 25 cent tick: 1 is tradeable, 2 is inactive, 3 has expired and 4 is
+/// ecp-only
+pub(crate) fn spec_table() -> ContractSpecTable {
+    let contract = |id: u64, active: bool, expires: &str, ecp: bool| {
+        format!(
+            r#"{{"id": {id}, "label": "BTC-Mini-{id}", "is_call": true, "active": {active}, "strike_price": 2500000, "min_increment": 25, "date_live": "2021-01-01 21:00:00+0000", "date_expires": "{expires}", "date_exercise": null, "underlying_asset": "CBTC", "collateral_asset": "CBTC", "derivative_type": "options_contract", "open_interest": 0, "is_next_day": false, "multiplier": 1, "is_ecp_only": {ecp}}}"#
+        )
+    };
+    let raw = format!(
+        r#"{{"data": [{}, {}, {}, {}]}}"#,
+        contract(1, true, "2099-12-31 21:00:00+0000", false),
+        contract(2, false, "2099-12-31 21:00:00+0000", false),
+        contract(3, true, "2021-06-25 21:00:00+0000", false),
+        contract(4, true, "2099-12-31 21:00:00+0000", true),
+    );
+    RawContractSpecTable::parse(&raw).unwrap().sanitize()
+}
@@ -1,8 +1,19 @@
-use std::str::FromStr;
+use std::collections::HashSet;
+use std::sync::Mutex;
 
-use serde::Deserialize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use ureq::Agent;
 
+use crate::error::{OrderError, OrderValidationError};
+use crate::journal::{Journal, JournalAction};
+use crate::limit::{RateLimiter, RequestKind};
+use crate::price::Price;
+use crate::retry::{Landed, RetryPolicy};
+use crate::table::ContractSpecTable;
+use crate::tracker::OrderTracker;
+use crate::ws::{ActionStatus, SanitizableMsg};
+
 // EXAMPLE BASE URL: https://trade.ledgerx.com/api
 
 /// thin wrapper for `ureq::Agent` that contains the order history + configuration info
@@ -17,6 +28,13 @@ pub struct OrderMngr<'a> {
 
     // history
     pub order_history: Vec<(OrderResponse, Order)>,
+    /// live state of every order sent from here, feed it action reports with `orders.apply`
+    pub orders: OrderTracker,
+    pub(crate) journal: Option<Journal>,
+    limiter: Option<RateLimiter>,
+    pub(crate) retry: Option<RetryPolicy>,
+    /// mids a retry has already matched to an order that isn't tracked yet
+    pub(crate) claimed: Mutex<HashSet<String>>,
 }
 
 impl<'a> OrderMngr<'a> {
@@ -26,32 +44,118 @@ impl<'a> OrderMngr<'a> {
             api_key: format!("JWT {}", api_key),
             agent: Agent::new(),
             order_history: Vec::new(),
+            orders: OrderTracker::new(),
+            journal: None,
+            limiter: None,
+            retry: None,
+            claimed: Mutex::new(HashSet::new()),
         }
     }
-    fn append(&mut self, resp: &OrderResponse, ord: &Order) {
+    pub(crate) fn append(&mut self, resp: &OrderResponse, ord: &Order) {
+        self.orders.track(resp, ord);
         self.order_history.push((resp.clone(), ord.to_owned()));
     }
-    fn send<T>(&self, action: &T) -> Result<T::OkType, ureq::Error>
+    /// paces everything sent from here on, nothing is paced until this is called
+    pub fn rate_limit(&mut self, limiter: RateLimiter) {
+        self.limiter = Some(limiter);
+    }
+    /// the limiter, for its metrics
+    pub fn limiter(&self) -> Option<&RateLimiter> {
+        self.limiter.as_ref()
+    }
+    pub(crate) fn throttle(&self, kind: RequestKind) -> Result<(), OrderError> {
+        match &self.limiter {
+            Some(l) => l.acquire(kind),
+            None => Ok(()),
+        }
+    }
+    /// retries failed requests from here on, see `RetryPolicy`. `None` (the default) never
+    /// retries.
+    pub fn retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry = policy;
+    }
+    pub(crate) fn send<T>(&self, action: &T) -> Result<T::OkType, OrderError>
     where
         T: SendWithMngr,
     {
-        let out = action.send_with_mngr(&self);
+        self.send_with_retry(action)
+    }
+    /// a single attempt, paced by the limiter
+    pub(crate) fn send_once<T>(&self, action: &T) -> Result<T::OkType, OrderError>
+    where
+        T: SendWithMngr,
+    {
+        self.throttle(action.kind())?;
+        let out = action.send_with_mngr(self);
 
+        if let Err(e) = &out {
+            self.rate_limited(action.kind(), e);
+        }
         return out;
     }
-    pub fn send_order(&mut self, ord: &Order) -> Result<OrderResponse, ureq::Error> {
-        let out = self.send(ord);
+    /// backs the limiter's `kind` bucket off if `e` says the exchange throttled us
+    pub(crate) fn rate_limited(&self, kind: RequestKind, e: &OrderError) {
+        if let (Some(l), OrderError::RateLimited { retry_after }) = (&self.limiter, e) {
+            l.throttled(kind, *retry_after);
+        }
+    }
+    /// journals `action` (if there's a journal) around sending it
+    fn send_journaled<T>(
+        &mut self,
+        action: &T,
+        entry: impl Fn(&T) -> JournalAction,
+        order_id: impl Fn(&T::OkType) -> Option<&str>,
+    ) -> Result<T::OkType, OrderError>
+    where
+        T: SendWithMngr + Sync,
+        T::OkType: Send,
+    {
+        let mut out = self.send_batch(std::slice::from_ref(action), entry, order_id);
+        return out.pop().unwrap();
+    }
+    pub fn send_order(&mut self, ord: &Order) -> Result<OrderResponse, OrderError> {
+        let out = self.send_journaled(
+            ord,
+            |o| JournalAction::Order(o.clone()),
+            |o| Some(o.order_id.as_str()),
+        );
         if let Ok(o) = out {
             self.append(&o, ord);
+            self.claimed.get_mut().unwrap().clear();
             return Ok(o);
         }
         return out;
     }
-    pub fn send_edit(&mut self, edit: &OrderEdit) -> Result<(), ureq::Error> {
-        self.send(edit)
+    pub fn send_edit(&mut self, edit: &OrderEdit) -> Result<(), OrderError> {
+        self.send_journaled(edit, |e| JournalAction::Edit(e.clone()), |_| None)
+    }
+    pub fn send_cancel(&mut self, cancel: &Cancel) -> Result<(), OrderError> {
+        self.send_journaled(cancel, |c| JournalAction::Cancel(c.clone()), |_| None)
     }
-    pub fn send_cancel(&mut self, cancel: &Cancel) -> Result<(), ureq::Error> {
-        self.send(cancel)
+    /// every order the exchange has resting for this account
+    pub fn open_orders(&self) -> Result<Vec<OrderRecord>, OrderError> {
+        self.send(&OpenOrders)
+    }
+    /// the exchange's view of a single order, open or not
+    pub fn order_status(&self, mid: &str) -> Result<OrderRecord, OrderError> {
+        self.send(&OrderStatus(mid.to_string()))
+    }
+    /// past orders as the exchange recorded them, unlike the in-process `order_history` this
+    /// survives a restart
+    pub fn order_history(&self) -> Result<Vec<OrderRecord>, OrderError> {
+        self.send(&OrderHistory)
+    }
+    pub(crate) fn base_url(&self) -> &str {
+        self.base_url
+    }
+    pub(crate) fn get(&self, path: &str) -> Result<ureq::Response, OrderError> {
+        let resp = self
+            .agent
+            .get(path)
+            .set("Authorization", &self.api_key)
+            .set("Accept", "application/json")
+            .call()?;
+        Ok(resp)
     }
 }
 
@@ -61,18 +165,49 @@ pub trait SendWithMngr {
     type OkType;
     /// Sends the order using a pre-defined and pre-stored OrderManager, this is preferred
     /// as the TCP connection can be recycled for later use + we can save config info.
-    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Self::OkType, ureq::Error>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Self::OkType, OrderError>;
+    /// which rate limit bucket this draws from, and how it's retried
+    fn kind(&self) -> RequestKind {
+        RequestKind::Read
+    }
+    /// after an attempt failed ambiguously, whether it reached the exchange after all. asked of
+    /// orders and edits before they're repeated (an `Unknown` one never is), and of cancels
+    /// whose retry found no order left.
+    fn landed(&self, _mngr: &OrderMngr) -> Landed<Self::OkType> {
+        Landed::Unknown
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    /// fills against the book at whatever price is there, no price is sent
+    Market,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapPurpose {
+    #[default]
+    Undisclosed,
+    BonaFideHedge,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Order {
-    pub order_type: String,
+    pub order_type: OrderType,
     pub contract_id: u64,
     pub is_ask: bool,
-    pub swap_purpose: String,
+    pub swap_purpose: SwapPurpose,
     pub size: u64,
-    pub price: u64,
+    pub price: Price,
     pub volatile: bool,
+    /// our own identifier for the order, e.g. the strategy that sent it. kept client-side
+    /// only, it's never sent to the exchange.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -82,72 +217,211 @@ pub struct OrderResponse {
 }
 
 impl Order {
-    pub fn new(contract_id: u64, is_ask: bool, price: f64, size: u64) -> Self {
+    /// a limit order
+    pub fn new(contract_id: u64, is_ask: bool, price: Price, size: u64) -> Self {
         Order {
-            order_type: String::from_str("limit").unwrap(),
+            order_type: OrderType::Limit,
             contract_id,
             is_ask,
-            swap_purpose: String::from_str("undisclosed").unwrap(),
+            swap_purpose: SwapPurpose::Undisclosed,
             size,
-            price: (price as u64) * 100,
+            price,
             volatile: false,
+            client_tag: None,
+        }
+    }
+    /// a market order, `price` is left at zero and not sent
+    pub fn market(contract_id: u64, is_ask: bool, size: u64) -> Self {
+        Order {
+            order_type: OrderType::Market,
+            ..Order::new(contract_id, is_ask, Price::ZERO, size)
         }
     }
     /// Denotes whether this trade is a bona-fide hedge or not (optional)
-    pub fn swap_purpose(&mut self, arg: &str) {
-        self.swap_purpose = String::from_str(arg).unwrap();
+    pub fn swap_purpose(&mut self, arg: SwapPurpose) {
+        self.swap_purpose = arg;
     }
     /// Specifies whether or not an order should auto-cancel at 4pm (optional)
     pub fn auto_cancel(&mut self, arg: bool) {
         self.volatile = arg;
     }
+    /// Tags the order with our own identifier, see `OrderMngr::orders_tagged` (optional)
+    pub fn client_tag(&mut self, arg: &str) {
+        self.client_tag = Some(arg.to_string());
+    }
+    /// moves the price onto the contract's tick, bids round down and asks round up so the
+    /// order is never more aggressive than asked for
+    pub fn round_to_increment(&mut self, min_increment: Price) {
+        self.price = if self.is_ask {
+            self.price.ceil_to(min_increment)
+        } else {
+            self.price.floor_to(min_increment)
+        };
+    }
+}
+
+/// builds an `Order` checked against the contract specs, so a bad order is refused here
+/// rather than by the exchange
+pub struct OrderBuilder<'a> {
+    specs: &'a ContractSpecTable,
+    contract_id: u64,
+    is_ask: bool,
+    size: u64,
+    order_type: OrderType,
+    price: Option<Price>,
+    swap_purpose: SwapPurpose,
+    volatile: bool,
+    ecp: bool,
+    client_tag: Option<String>,
+}
+
+impl<'a> OrderBuilder<'a> {
+    pub fn new(specs: &'a ContractSpecTable, contract_id: u64, is_ask: bool, size: u64) -> Self {
+        OrderBuilder {
+            specs,
+            contract_id,
+            is_ask,
+            size,
+            order_type: OrderType::Limit,
+            price: None,
+            swap_purpose: SwapPurpose::Undisclosed,
+            volatile: false,
+            ecp: false,
+            client_tag: None,
+        }
+    }
+    /// makes this a limit order at `price`
+    pub fn price(mut self, price: Price) -> Self {
+        self.order_type = OrderType::Limit;
+        self.price = Some(price);
+        self
+    }
+    pub fn market(mut self) -> Self {
+        self.order_type = OrderType::Market;
+        self.price = None;
+        self
+    }
+    pub fn swap_purpose(mut self, arg: SwapPurpose) -> Self {
+        self.swap_purpose = arg;
+        self
+    }
+    pub fn auto_cancel(mut self, arg: bool) -> Self {
+        self.volatile = arg;
+        self
+    }
+    /// Tags the order with our own identifier, see `OrderMngr::orders_tagged` (optional)
+    pub fn client_tag(mut self, arg: &str) -> Self {
+        self.client_tag = Some(arg.to_string());
+        self
+    }
+    /// whether the account is an eligible contract participant, needed for ecp-only contracts
+    pub fn ecp(mut self, arg: bool) -> Self {
+        self.ecp = arg;
+        self
+    }
+    pub fn build(self) -> Result<Order, OrderValidationError> {
+        let spec = self
+            .specs
+            .id_table
+            .get(&self.contract_id)
+            .ok_or(OrderValidationError::UnknownContract(self.contract_id))?;
+
+        if !spec.active() {
+            return Err(OrderValidationError::Inactive(self.contract_id));
+        }
+        // without a readable expiry it's left to the exchange
+        if let Some(date_expires) = spec.date_expires() {
+            if date_expires <= Utc::now() {
+                return Err(OrderValidationError::Expired {
+                    contract_id: self.contract_id,
+                    date_expires,
+                });
+            }
+        }
+        if spec.is_ecp_only() && !self.ecp {
+            return Err(OrderValidationError::EcpOnly(self.contract_id));
+        }
+        if self.size == 0 {
+            return Err(OrderValidationError::ZeroSize);
+        }
+
+        let mut out = match self.order_type {
+            OrderType::Market => Order::market(self.contract_id, self.is_ask, self.size),
+            OrderType::Limit => {
+                let price = self.price.ok_or(OrderValidationError::MissingPrice)?;
+                let price = price.check_increment(spec.min_increment())?;
+                Order::new(self.contract_id, self.is_ask, price, self.size)
+            }
+        };
+        out.swap_purpose(self.swap_purpose);
+        out.auto_cancel(self.volatile);
+        out.client_tag = self.client_tag;
+        Ok(out)
+    }
+}
+
+/// wire format of `POST /orders`
+#[derive(Serialize, Debug)]
+pub(crate) struct OrderRequest {
+    order_type: OrderType,
+    contract_id: u64,
+    is_ask: bool,
+    swap_purpose: SwapPurpose,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<Price>,
+    volatile: bool,
+}
+
+impl Order {
+    pub(crate) fn request(&self) -> OrderRequest {
+        OrderRequest {
+            order_type: self.order_type,
+            contract_id: self.contract_id,
+            is_ask: self.is_ask,
+            swap_purpose: self.swap_purpose,
+            size: self.size,
+            price: match self.order_type {
+                OrderType::Limit => Some(self.price),
+                OrderType::Market => None,
+            },
+            volatile: self.volatile,
+        }
+    }
 }
 
 impl SendWithMngr for Order {
     type OkType = OrderResponse;
-    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<OrderResponse, ureq::Error> {
+    fn kind(&self) -> RequestKind {
+        RequestKind::Order
+    }
+    fn landed(&self, mngr: &OrderMngr) -> Landed<OrderResponse> {
+        self.find_landed(mngr)
+    }
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<OrderResponse, OrderError> {
         let path = format!("{}/orders", mngr.base_url);
 
-        let data = format!(
-            "{{
-                \"order_type\": \"{}\",
-                \"contract_id\": {},
-                \"is_ask\": {},
-                \"swap_purpose\": \"{}\",
-                \"size\": {},
-                \"price\": {},
-                \"volatile\": {}
-           }}",
-            self.order_type,
-            self.contract_id,
-            self.is_ask,
-            self.swap_purpose,
-            self.size,
-            self.price,
-            self.volatile,
-        );
-
         let resp = mngr
             .agent
             .post(&path)
             .set("Authorization", &mngr.api_key)
             .set("accept", "application/json")
-            .set("content-type", "application/json")
-            .send_string(&data)?;
+            .send_json(self.request())?;
 
-        let ord_resp: OrderResponse = serde_json::from_reader(resp.into_reader()).unwrap();
+        let ord_resp: OrderResponse = serde_json::from_reader(resp.into_reader())?;
         Ok(ord_resp)
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct OrderEdit {
-    order_id: String,
-    contract_id: u64,
-    price: u64,
-    size: u64,
+    pub(crate) order_id: String,
+    pub(crate) contract_id: u64,
+    pub(crate) price: Price,
+    pub(crate) size: u64,
 }
 impl OrderEdit {
-    pub fn new(order_id: String, contract_id: u64, price: u64, size: u64) -> Self {
+    pub fn new(order_id: String, contract_id: u64, price: Price, size: u64) -> Self {
         OrderEdit {
             order_id,
             contract_id,
@@ -157,34 +431,50 @@ impl OrderEdit {
     }
 }
 
+impl OrderEdit {
+    pub(crate) fn path(&self, base_url: &str) -> String {
+        format!("{}/orders/{}/edit", base_url, self.order_id)
+    }
+    pub(crate) fn request(&self) -> EditRequest {
+        EditRequest {
+            contract_id: self.contract_id,
+            size: self.size,
+            price: self.price,
+        }
+    }
+}
+
+/// wire format of `POST /orders/{mid}/edit`
+#[derive(Serialize, Debug)]
+pub(crate) struct EditRequest {
+    contract_id: u64,
+    size: u64,
+    price: Price,
+}
+
 impl SendWithMngr for OrderEdit {
     type OkType = ();
-    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<(), ureq::Error> {
-        let path = &format!("{}/orders/{}/edit", mngr.base_url, self.order_id,);
-
-        let data = format!(
-            "{{
-                \"contract_id\": {},
-                \"size\": {},
-                \"price\": {},
-           }}",
-            self.contract_id, self.size, self.price,
-        );
+    fn kind(&self) -> RequestKind {
+        RequestKind::Order
+    }
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<(), OrderError> {
+        let path = &self.path(mngr.base_url);
 
         let resp = mngr
             .agent
             .post(path)
             .set("Authorization", &mngr.api_key)
             .set("Accept", "application/json")
-            .set("content-type", "application/json")
-            .send_string(&data)
+            .send_json(self.request())
+            .map_err(|e| OrderError::from(e).on_order())
             .and(Ok(()));
 
         return resp;
     }
 }
 
-pub struct Cancel(Option<(String, u64)>);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cancel(pub(crate) Option<(String, u64)>);
 
 impl Cancel {
     pub fn one(order_id: String, contract_id: u64) -> Self {
@@ -195,48 +485,199 @@ impl Cancel {
     }
 }
 
+impl Cancel {
+    pub(crate) fn path(&self, base_url: &str) -> String {
+        match &self.0 {
+            Some((order_id, _)) => format!("{}/orders/{}", base_url, order_id),
+            None => format!("{}/orders", base_url),
+        }
+    }
+    /// cancelling everything is sent without a body
+    pub(crate) fn request(&self) -> Option<CancelRequest> {
+        self.0.as_ref().map(|(_, contract_id)| CancelRequest {
+            contract_id: *contract_id,
+        })
+    }
+}
+
+/// wire format of `DELETE /orders/{mid}`
+#[derive(Serialize, Debug)]
+pub(crate) struct CancelRequest {
+    contract_id: u64,
+}
+
 impl SendWithMngr for Cancel {
     type OkType = ();
-    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<(), ureq::Error> {
-        if let Some((order_id, contract_id)) = &self.0 {
-            let path = &format!("{}/orders/{}", mngr.base_url, order_id);
-
-            let data = format!(
-                "{{
-                    \"contract_id\": {},
-               }}",
-                contract_id,
-            );
-
-            let resp = mngr
-                .agent
-                .delete(path)
-                .set("Authorization", &mngr.api_key)
-                .set("Accept", "application/json")
-                .set("content-type", "application/json")
-                .send_string(&data)
-                .and(Ok(()));
-
-            return resp;
-        } else {
-            let path = &format!("{}/orders", mngr.base_url);
+    fn kind(&self) -> RequestKind {
+        RequestKind::Cancel
+    }
+    /// the order is gone, so an earlier attempt must have cancelled it
+    fn landed(&self, _mngr: &OrderMngr) -> Landed<()> {
+        Landed::Yes(())
+    }
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<(), OrderError> {
+        let path = &self.path(mngr.base_url);
+        let req = mngr
+            .agent
+            .delete(path)
+            .set("Authorization", &mngr.api_key)
+            .set("Accept", "application/json");
+
+        let resp = match self.request() {
+            Some(data) => req.send_json(data),
+            None => req.call(),
+        };
+
+        let out = resp.map_err(OrderError::from);
+        return match self.0 {
+            Some(_) => out.map_err(OrderError::on_order).and(Ok(())),
+            None => out.and(Ok(())),
+        };
+    }
+}
 
-            let resp = mngr
-                .agent
-                .delete(path)
-                .set("Authorization", &mngr.api_key)
-                .set("Accept", "application/json")
-                .call()
-                .and(Ok(()));
+//
+// QUERIES
+//
 
-            return resp;
+/// an order as the exchange reports it from the REST endpoints
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderRecord {
+    pub mid: String,
+    pub contract_id: u64,
+    pub order_type: OrderType,
+    pub status: ActionStatus,
+    pub status_reason: u64,
+    pub is_ask: bool,
+
+    pub price: Price,
+    pub size: u64,
+    pub filled_price: Price,
+    pub filled_size: u64,
+    pub open_size: u64,
+
+    pub volatile: bool,
+    pub clock: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawOrderRecord {
+    mid: String,
+    contract_id: u64,
+    #[serde(default)]
+    order_type: OrderType,
+    status_type: u64,
+    #[serde(default)]
+    status_reason: u64,
+    is_ask: bool,
+
+    #[serde(default)]
+    price: u64,
+    size: u64,
+    #[serde(default)]
+    filled_price: u64,
+    #[serde(default)]
+    filled_size: u64,
+    #[serde(default)]
+    open_size: u64,
+
+    #[serde(default)]
+    volatile: bool,
+    #[serde(default)]
+    clock: u64,
+}
+
+impl<'a> SanitizableMsg<'a> for RawOrderRecord {
+    type OUT = OrderRecord;
+    fn sanitize(self) -> Self::OUT {
+        OrderRecord {
+            status: ActionStatus::from_code(self.status_type, self.open_size),
+            mid: self.mid,
+            contract_id: self.contract_id,
+            order_type: self.order_type,
+            status_reason: self.status_reason,
+            is_ask: self.is_ask,
+            price: Price::from_cents(self.price),
+            size: self.size,
+            filled_price: Price::from_cents(self.filled_price),
+            filled_size: self.filled_size,
+            open_size: self.open_size,
+            volatile: self.volatile,
+            clock: self.clock,
         }
     }
 }
 
+/// the exchange wraps every REST payload in `{"data": ...}`
+#[derive(Deserialize, Debug)]
+pub(crate) struct RawData<T> {
+    pub data: T,
+}
+
+fn records(raw: Vec<RawOrderRecord>) -> Vec<OrderRecord> {
+    raw.into_iter().map(|r| r.sanitize()).collect()
+}
+
+/// `GET /open-orders`
+pub struct OpenOrders;
+
+impl OpenOrders {
+    pub(crate) fn path(&self, base_url: &str) -> String {
+        format!("{}/open-orders", base_url)
+    }
+}
+
+impl SendWithMngr for OpenOrders {
+    type OkType = Vec<OrderRecord>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Vec<OrderRecord>, OrderError> {
+        let resp = mngr.get(&self.path(mngr.base_url))?;
+        let raw: RawData<Vec<RawOrderRecord>> = serde_json::from_reader(resp.into_reader())?;
+        Ok(records(raw.data))
+    }
+}
+
+/// `GET /orders/{mid}`
+pub struct OrderStatus(pub String);
+
+impl OrderStatus {
+    pub(crate) fn path(&self, base_url: &str) -> String {
+        format!("{}/orders/{}", base_url, self.0)
+    }
+}
+
+impl SendWithMngr for OrderStatus {
+    type OkType = OrderRecord;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<OrderRecord, OrderError> {
+        let resp = mngr
+            .get(&self.path(mngr.base_url))
+            .map_err(OrderError::on_order)?;
+        let raw: RawData<RawOrderRecord> = serde_json::from_reader(resp.into_reader())?;
+        Ok(raw.data.sanitize())
+    }
+}
+
+/// `GET /order-history`
+pub struct OrderHistory;
+
+impl OrderHistory {
+    pub(crate) fn path(&self, base_url: &str) -> String {
+        format!("{}/order-history", base_url)
+    }
+}
+
+impl SendWithMngr for OrderHistory {
+    type OkType = Vec<OrderRecord>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<Vec<OrderRecord>, OrderError> {
+        let resp = mngr.get(&self.path(mngr.base_url))?;
+        let raw: RawData<Vec<RawOrderRecord>> = serde_json::from_reader(resp.into_reader())?;
+        Ok(records(raw.data))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::{spec_table, MockServer};
     const API_KEY: &str = ""; //env!("LEDGERX_TEST_API_KEY");
 
     fn setup<'a>() -> OrderMngr<'a> {
@@ -247,21 +688,311 @@ mod tests {
         setup();
     }
 
+    #[test]
+    fn send_order_records_history() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") => (200, r#"{"mid": "4ff9c5a1"}"#.to_string()),
+            _ => (404, "{}".to_string()),
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let out = om
+            .send_order(&Order::new(22252392, true, Price::from_dollars(2.0), 3))
+            .unwrap();
+        assert_eq!(out.order_id, "4ff9c5a1");
+        assert_eq!(om.order_history.len(), 1);
+        assert!(om.orders.get("4ff9c5a1").unwrap().is_open());
+
+        let reqs = server.requests();
+        assert_eq!(reqs[0].authorization.as_deref(), Some("JWT key"));
+        let body: serde_json::Value = serde_json::from_str(&reqs[0].body).unwrap();
+        assert_eq!(body["contract_id"], 22252392);
+        assert_eq!(body["is_ask"], true);
+    }
+
     #[test]
     fn place_order() {
-        let mut om = setup();
-        let out = Order::new(22252392, false, 1.0, 1).send_with_mngr(&mut om);
-        println!(
-            "{:?}",
-            out.or_else(|x| { Err(x.into_response().unwrap().into_string().unwrap()) })
-        );
+        let om = setup();
+        let out = Order::new(22252392, false, Price::from_dollars(1.0), 1).send_with_mngr(&om);
+        println!("{:?}", out);
     }
 
     #[test]
     fn cancel_all() {
-        let mut om = setup();
-        let out = Cancel::all().send_with_mngr(&mut om);
+        let om = setup();
+        let out = Cancel::all().send_with_mngr(&om);
 
         println!("{:?}", out);
     }
+
+    #[test]
+    fn exchange_errors_are_classified() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") => (
+                400,
+                r#"{"error": {"code": 62, "message": "Insufficient collateral for order"}}"#
+                    .to_string(),
+            ),
+            (_, "/orders/gone/edit") => (404, r#"{"error": "order not found"}"#.to_string()),
+            (_, "/orders/throttled") => (429, "slow down".to_string()),
+            _ => (401, r#"{"message": "invalid token"}"#.to_string()),
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let out = om.send_order(&Order::new(22252392, false, Price::from_dollars(1.0), 1));
+        assert!(matches!(out, Err(OrderError::InsufficientCollateral(_))));
+        assert!(om.order_history.is_empty());
+
+        let out = om.send_edit(&OrderEdit::new(
+            "gone".to_string(),
+            22252392,
+            Price::from_cents(100),
+            1,
+        ));
+        assert!(matches!(out, Err(OrderError::OrderNotFound(_))));
+
+        let out = om.send_cancel(&Cancel::one("throttled".to_string(), 22252392));
+        assert_eq!(out, Err(OrderError::RateLimited { retry_after: None }));
+
+        let out = om.send_cancel(&Cancel::all());
+        assert_eq!(
+            out,
+            Err(OrderError::AuthFailure("invalid token".to_string()))
+        );
+    }
+
+    #[test]
+    fn error_responses() {
+        assert_eq!(
+            OrderError::from_response(429, Some("3"), ""),
+            OrderError::RateLimited {
+                retry_after: Some(std::time::Duration::from_secs(3))
+            }
+        );
+        assert!(matches!(
+            OrderError::from_response(
+                400,
+                None,
+                r#"{"error": "price is not a multiple of min_increment"}"#
+            ),
+            OrderError::InvalidPriceIncrement(_)
+        ));
+        assert!(matches!(
+            OrderError::from_response(400, None, r#"{"error": "unknown contract_id"}"#),
+            OrderError::UnknownContract(_)
+        ));
+        // a missing order that mentions its contract is still a missing order
+        assert!(matches!(
+            OrderError::from_response(
+                404,
+                None,
+                r#"{"error": "no open order with that id on this contract"}"#
+            )
+            .on_order(),
+            OrderError::OrderNotFound(_)
+        ));
+        // but a 404 off an order endpoint is just a missing endpoint
+        assert!(matches!(
+            OrderError::from_response(404, None, r#"{"error": "no such route"}"#),
+            OrderError::Exchange { status: 404, .. }
+        ));
+        // nor does every "tick" mean a bad price increment
+        assert!(matches!(
+            OrderError::from_response(400, None, r#"{"error": "invalid ticket id"}"#),
+            OrderError::Exchange { status: 400, .. }
+        ));
+        // nor is every message about a contract an unknown one
+        assert!(matches!(
+            OrderError::from_response(400, None, r#"{"error": "contract is not yet active"}"#),
+            OrderError::Exchange { status: 400, .. }
+        ));
+        assert_eq!(
+            OrderError::from_response(500, None, "<html>oops</html>"),
+            OrderError::Exchange {
+                status: 500,
+                message: "<html>oops</html>".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn unreachable_exchange() {
+        // nothing listens on the discard port
+        let mut om = OrderMngr::new("http://127.0.0.1:9", "key");
+        let out = om.send_order(&Order::new(22252392, false, Price::from_dollars(1.0), 1));
+        assert!(matches!(out, Err(OrderError::ConnectionFailed(_))));
+    }
+
+    #[test]
+    fn builder_validates_against_specs() {
+        let specs = spec_table();
+        let ok = OrderBuilder::new(&specs, 1, false, 2)
+            .price(Price::from_cents(175))
+            .swap_purpose(SwapPurpose::BonaFideHedge)
+            .build()
+            .unwrap();
+        assert_eq!(ok.price, Price::from_cents(175));
+        assert_eq!(ok.swap_purpose, SwapPurpose::BonaFideHedge);
+
+        let build = |id, price: Option<u64>, size| {
+            let b = OrderBuilder::new(&specs, id, false, size);
+            match price {
+                Some(p) => b.price(Price::from_cents(p)),
+                None => b,
+            }
+            .build()
+            .unwrap_err()
+        };
+        assert_eq!(
+            build(99, Some(175), 1),
+            OrderValidationError::UnknownContract(99)
+        );
+        assert_eq!(build(2, Some(175), 1), OrderValidationError::Inactive(2));
+        assert!(matches!(
+            build(3, Some(175), 1),
+            OrderValidationError::Expired { contract_id: 3, .. }
+        ));
+        assert_eq!(build(4, Some(175), 1), OrderValidationError::EcpOnly(4));
+        assert_eq!(build(1, Some(175), 0), OrderValidationError::ZeroSize);
+        assert_eq!(build(1, None, 1), OrderValidationError::MissingPrice);
+        assert_eq!(
+            build(1, Some(160), 1),
+            OrderValidationError::InvalidIncrement {
+                price: Price::from_cents(160),
+                min_increment: Price::from_cents(25)
+            }
+        );
+
+        assert!(OrderBuilder::new(&specs, 4, true, 1)
+            .market()
+            .ecp(true)
+            .build()
+            .is_ok());
+    }
+
+    fn golden_server() -> MockServer {
+        MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") => (200, r#"{"mid": "4ff9c5a1"}"#.to_string()),
+            _ => (200, "{}".to_string()),
+        })
+    }
+
+    #[test]
+    fn golden_order_payload() {
+        let server = golden_server();
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let mut ord = Order::new(22252392, true, Price::from_dollars(2.0), 3);
+        ord.auto_cancel(true);
+        om.send_order(&ord).unwrap();
+
+        let mut ord = Order::market(22252392, false, 1);
+        ord.swap_purpose(SwapPurpose::BonaFideHedge);
+        om.send_order(&ord).unwrap();
+
+        let reqs = server.requests();
+        assert_eq!(
+            reqs[0].body,
+            r#"{"order_type":"limit","contract_id":22252392,"is_ask":true,"swap_purpose":"undisclosed","size":3,"price":200,"volatile":true}"#
+        );
+        // market orders go out without a price
+        assert_eq!(
+            reqs[1].body,
+            r#"{"order_type":"market","contract_id":22252392,"is_ask":false,"swap_purpose":"bona_fide_hedge","size":1,"volatile":false}"#
+        );
+    }
+
+    #[test]
+    fn fractional_prices_are_exact() {
+        let server = golden_server();
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let mut ord = Order::new(22252392, false, Price::from_dollars(1.75), 1);
+        om.send_order(&ord).unwrap();
+        ord.price = Price::from_cents(190);
+        ord.round_to_increment(Price::from_cents(25));
+        assert_eq!(ord.price, Price::from_cents(175));
+        ord.is_ask = true;
+        ord.price = Price::from_cents(160);
+        ord.round_to_increment(Price::from_cents(25));
+        assert_eq!(ord.price, Price::from_cents(175));
+
+        assert!(server.requests()[0].body.contains(r#""price":175"#));
+    }
+
+    #[test]
+    fn golden_edit_and_cancel_payloads() {
+        let server = golden_server();
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        om.send_edit(&OrderEdit::new(
+            "4ff9c5a1".to_string(),
+            22252392,
+            Price::from_cents(175),
+            2,
+        ))
+        .unwrap();
+        let reqs = server.requests();
+        assert_eq!(reqs[0].method, "POST");
+        assert_eq!(reqs[0].url, "/orders/4ff9c5a1/edit");
+        assert_eq!(
+            reqs[0].body,
+            r#"{"contract_id":22252392,"size":2,"price":175}"#
+        );
+
+        om.send_cancel(&Cancel::one("4ff9c5a1".to_string(), 22252392))
+            .unwrap();
+        let reqs = server.requests();
+        assert_eq!(reqs[1].method, "DELETE");
+        assert_eq!(reqs[1].url, "/orders/4ff9c5a1");
+        assert_eq!(reqs[1].body, r#"{"contract_id":22252392}"#);
+
+        om.send_cancel(&Cancel::all()).unwrap();
+        let reqs = server.requests();
+        assert_eq!(reqs[2].method, "DELETE");
+        assert_eq!(reqs[2].url, "/orders");
+        assert_eq!(reqs[2].body, "");
+    }
+
+    #[test]
+    fn queries_parse_exchange_records() {
+        let server = MockServer::start(|req| {
+            match (req.method.as_str(), req.url.as_str()) {
+            ("GET", "/open-orders") => (
+                200,
+                r#"{"data": [{"mid": "a1", "contract_id": 22252392, "order_type": "limit", "status_type": 201, "is_ask": false, "price": 175, "size": 4, "filled_price": 175, "filled_size": 1, "open_size": 3, "clock": 9}]}"#
+                    .to_string(),
+            ),
+            ("GET", "/orders/a1") => (
+                200,
+                r#"{"data": {"mid": "a1", "contract_id": 22252392, "status_type": 203, "is_ask": false, "price": 175, "size": 4}}"#
+                    .to_string(),
+            ),
+            ("GET", "/orders/gone") => (404, r#"{"error": "order not found"}"#.to_string()),
+            ("GET", "/order-history") => (200, r#"{"data": []}"#.to_string()),
+            _ => (500, "{}".to_string()),
+        }
+        });
+        let om = OrderMngr::new(&server.url, "key");
+
+        let open = om.open_orders().unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].status, ActionStatus::PartiallyFilled);
+        assert_eq!(open[0].price, Price::from_cents(175));
+        assert_eq!(open[0].open_size, 3);
+
+        let one = om.order_status("a1").unwrap();
+        assert_eq!(one.status, ActionStatus::Cancelled);
+        assert_eq!(one.order_type, OrderType::Limit);
+        assert!(matches!(
+            om.order_status("gone"),
+            Err(OrderError::OrderNotFound(_))
+        ));
+
+        assert!(om.order_history().unwrap().is_empty());
+        assert!(server
+            .requests()
+            .iter()
+            .all(|r| r.authorization.as_deref() == Some("JWT key")));
+    }
 }
@@ -0,0 +1,288 @@
+use std::sync::Mutex;
+
+use futures_util::future::join_all;
+use reqwest::{Client, Response};
+
+use crate::error::OrderError;
+use crate::order::{
+    Cancel, OpenOrders, Order, OrderEdit, OrderHistory, OrderRecord, OrderResponse, OrderStatus,
+    RawData, RawOrderRecord,
+};
+use crate::ws::SanitizableMsg;
+
+/// async counterpart of `OrderMngr`. every method takes `&self`, so any number of orders,
+/// edits and cancels can be in flight at once over the client's connection pool.
+pub struct AsyncOrderMngr<'a> {
+    // config stuff
+    base_url: &'a str,
+    api_key: String,
+
+    // http client
+    client: Client,
+
+    // history
+    pub order_history: Mutex<Vec<(OrderResponse, Order)>>,
+}
+
+impl<'a> AsyncOrderMngr<'a> {
+    pub fn new(base_url: &'a str, api_key: &'a str) -> Self {
+        AsyncOrderMngr {
+            base_url,
+            api_key: format!("JWT {}", api_key),
+            client: Client::new(),
+            order_history: Mutex::new(Vec::new()),
+        }
+    }
+    fn append(&self, resp: &OrderResponse, ord: &Order) {
+        self.order_history
+            .lock()
+            .unwrap()
+            .push((resp.clone(), ord.to_owned()));
+    }
+    async fn send<T>(&self, action: &T) -> Result<T::OkType, OrderError>
+    where
+        T: SendWithAsyncMngr,
+    {
+        action.send_with_async_mngr(self).await
+    }
+    pub async fn send_order(&self, ord: &Order) -> Result<OrderResponse, OrderError> {
+        let out = self.send(ord).await;
+        if let Ok(o) = &out {
+            self.append(o, ord);
+        }
+        return out;
+    }
+    pub async fn send_edit(&self, edit: &OrderEdit) -> Result<(), OrderError> {
+        self.send(edit).await
+    }
+    pub async fn send_cancel(&self, cancel: &Cancel) -> Result<(), OrderError> {
+        self.send(cancel).await
+    }
+    pub async fn open_orders(&self) -> Result<Vec<OrderRecord>, OrderError> {
+        self.send(&OpenOrders).await
+    }
+    pub async fn order_status(&self, mid: &str) -> Result<OrderRecord, OrderError> {
+        self.send(&OrderStatus(mid.to_string())).await
+    }
+    pub async fn order_history(&self) -> Result<Vec<OrderRecord>, OrderError> {
+        self.send(&OrderHistory).await
+    }
+    async fn get<T>(&self, path: String) -> Result<T, OrderError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let resp = self
+            .client
+            .get(path)
+            .header("Authorization", &self.api_key)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        Ok(check(resp).await?.json().await?)
+    }
+    /// sends every order concurrently, results are in the same order as `ords`
+    pub async fn send_orders(&self, ords: &[Order]) -> Vec<Result<OrderResponse, OrderError>> {
+        join_all(ords.iter().map(|o| self.send_order(o))).await
+    }
+    /// sends every edit concurrently, results are in the same order as `edits`
+    pub async fn send_edits(&self, edits: &[OrderEdit]) -> Vec<Result<(), OrderError>> {
+        join_all(edits.iter().map(|e| self.send_edit(e))).await
+    }
+    /// sends every cancel concurrently, results are in the same order as `cancels`
+    pub async fn send_cancels(&self, cancels: &[Cancel]) -> Vec<Result<(), OrderError>> {
+        join_all(cancels.iter().map(|c| self.send_cancel(c))).await
+    }
+}
+
+/// `SendWithMngr` for `AsyncOrderMngr`
+// only ever awaited from this crate's own mngr, so the futures' auto traits don't need spelling out
+#[allow(async_fn_in_trait)]
+pub trait SendWithAsyncMngr {
+    type OkType;
+    async fn send_with_async_mngr(&self, mngr: &AsyncOrderMngr)
+        -> Result<Self::OkType, OrderError>;
+}
+
+/// turns a non-2xx response into the matching `OrderError`
+async fn check(resp: Response) -> Result<Response, OrderError> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+    let status = resp.status().as_u16();
+    let retry_after = resp
+        .headers()
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let body = resp.text().await.unwrap_or_default();
+    Err(OrderError::from_response(
+        status,
+        retry_after.as_deref(),
+        &body,
+    ))
+}
+
+impl SendWithAsyncMngr for Order {
+    type OkType = OrderResponse;
+    async fn send_with_async_mngr(
+        &self,
+        mngr: &AsyncOrderMngr<'_>,
+    ) -> Result<OrderResponse, OrderError> {
+        let path = format!("{}/orders", mngr.base_url);
+
+        let resp = mngr
+            .client
+            .post(&path)
+            .header("Authorization", &mngr.api_key)
+            .header("accept", "application/json")
+            .json(&self.request())
+            .send()
+            .await?;
+
+        Ok(check(resp).await?.json().await?)
+    }
+}
+
+impl SendWithAsyncMngr for OrderEdit {
+    type OkType = ();
+    async fn send_with_async_mngr(&self, mngr: &AsyncOrderMngr<'_>) -> Result<(), OrderError> {
+        let resp = mngr
+            .client
+            .post(self.path(mngr.base_url))
+            .header("Authorization", &mngr.api_key)
+            .header("Accept", "application/json")
+            .json(&self.request())
+            .send()
+            .await?;
+
+        check(resp).await.map_err(OrderError::on_order).and(Ok(()))
+    }
+}
+
+impl SendWithAsyncMngr for Cancel {
+    type OkType = ();
+    async fn send_with_async_mngr(&self, mngr: &AsyncOrderMngr<'_>) -> Result<(), OrderError> {
+        let mut req = mngr
+            .client
+            .delete(self.path(mngr.base_url))
+            .header("Authorization", &mngr.api_key)
+            .header("Accept", "application/json");
+        if let Some(data) = self.request() {
+            req = req.json(&data);
+        }
+
+        let out = check(req.send().await?).await;
+        match self.0 {
+            Some(_) => out.map_err(OrderError::on_order).and(Ok(())),
+            None => out.and(Ok(())),
+        }
+    }
+}
+
+impl SendWithAsyncMngr for OpenOrders {
+    type OkType = Vec<OrderRecord>;
+    async fn send_with_async_mngr(
+        &self,
+        mngr: &AsyncOrderMngr<'_>,
+    ) -> Result<Vec<OrderRecord>, OrderError> {
+        let raw: RawData<Vec<RawOrderRecord>> = mngr.get(self.path(mngr.base_url)).await?;
+        Ok(raw.data.into_iter().map(|r| r.sanitize()).collect())
+    }
+}
+
+impl SendWithAsyncMngr for OrderStatus {
+    type OkType = OrderRecord;
+    async fn send_with_async_mngr(
+        &self,
+        mngr: &AsyncOrderMngr<'_>,
+    ) -> Result<OrderRecord, OrderError> {
+        let raw: RawData<RawOrderRecord> = mngr
+            .get(self.path(mngr.base_url))
+            .await
+            .map_err(OrderError::on_order)?;
+        Ok(raw.data.sanitize())
+    }
+}
+
+impl SendWithAsyncMngr for OrderHistory {
+    type OkType = Vec<OrderRecord>;
+    async fn send_with_async_mngr(
+        &self,
+        mngr: &AsyncOrderMngr<'_>,
+    ) -> Result<Vec<OrderRecord>, OrderError> {
+        let raw: RawData<Vec<RawOrderRecord>> = mngr.get(self.path(mngr.base_url)).await?;
+        Ok(raw.data.into_iter().map(|r| r.sanitize()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::price::Price;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn pipelines_orders() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+            MockServer::start(move |req| {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                match (req.method.as_str(), req.url.as_str()) {
+                    ("POST", "/orders") => (200, r#"{"mid": "m"}"#.to_string()),
+                    ("DELETE", _) => (200, "{}".to_string()),
+                    _ => (404, "{}".to_string()),
+                }
+            })
+        };
+        let om = AsyncOrderMngr::new(&server.url, "key");
+
+        let ords: Vec<Order> = (0..10)
+            .map(|i| Order::new(22252392 + i, false, Price::from_dollars(1.0), 1))
+            .collect();
+        let out = om.send_orders(&ords).await;
+
+        assert!(out.iter().all(|r| r.is_ok()), "{:?}", out);
+        assert_eq!(om.order_history.lock().unwrap().len(), 10);
+        assert!(max_in_flight.load(Ordering::SeqCst) > 1);
+
+        let reqs = server.requests();
+        assert_eq!(reqs.len(), 10);
+        assert!(reqs
+            .iter()
+            .all(|r| r.authorization.as_deref() == Some("JWT key")));
+
+        let cancels = vec![
+            Cancel::one("a".to_string(), 1),
+            Cancel::one("b".to_string(), 2),
+        ];
+        let out = om.send_cancels(&cancels).await;
+        assert!(out.iter().all(|r| r.is_ok()), "{:?}", out);
+    }
+
+    #[tokio::test]
+    async fn http_errors_are_errors() {
+        let server = MockServer::start(|_| (400, r#"{"error": "nope"}"#.to_string()));
+        let om = AsyncOrderMngr::new(&server.url, "key");
+
+        let out = om
+            .send_order(&Order::new(22252392, false, Price::from_dollars(1.0), 1))
+            .await;
+        assert_eq!(
+            out.unwrap_err(),
+            OrderError::Exchange {
+                status: 400,
+                message: "nope".to_string()
+            }
+        );
+        assert!(om.order_history.lock().unwrap().is_empty());
+    }
+}
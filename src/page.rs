@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::error::OrderError;
+use crate::order::{OrderMngr, SendWithMngr};
+use crate::ws::SanitizableMsg;
+
+/// rows requested per page
+pub const PAGE_LIMIT: u64 = 200;
+
+/// one page of a listing endpoint. the exchange either hands back a `next` link (cursor) or
+/// just the `total_count`, in which case we page by offset.
+#[derive(Deserialize, Debug)]
+pub struct RawPage<T> {
+    #[serde(default)]
+    meta: RawPageMeta,
+    data: Vec<T>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RawPageMeta {
+    next: Option<String>,
+    total_count: Option<u64>,
+}
+
+/// walks every page of a listing endpoint, fetching the next one only once the current one
+/// has been consumed. an error is yielded once and ends the iteration.
+pub struct Paginated<'m, 'a, R> {
+    mngr: &'m OrderMngr<'a>,
+    next: Option<String>,
+    // the first page's url, offsets are appended to it
+    first: String,
+    offset: u64,
+    buf: VecDeque<R>,
+}
+
+impl<'m, 'a, R> Paginated<'m, 'a, R>
+where
+    R: SanitizableMsg<'static>,
+    RawPage<R>: DeserializeOwned,
+{
+    /// `path` is relative to the mngr's base url and may already carry a query string
+    pub(crate) fn new(mngr: &'m OrderMngr<'a>, path: &str) -> Self {
+        let first = first_page(mngr.base_url(), path);
+        Paginated {
+            mngr,
+            next: Some(first.clone()),
+            first,
+            offset: 0,
+            buf: VecDeque::new(),
+        }
+    }
+
+    fn fetch(&mut self, url: &str) -> Result<(), OrderError> {
+        let page = self.mngr.send(&PageRequest::<R>(url, PhantomData))?;
+        self.next = page.next_url(self.mngr.base_url(), &self.first, self.offset);
+        self.offset += page.data.len() as u64;
+        self.buf.extend(page.data);
+        Ok(())
+    }
+}
+
+/// the first page of the listing at `path` (relative to `base_url`, it may already carry a
+/// query string)
+pub(crate) fn first_page(base_url: &str, path: &str) -> String {
+    let sep = if path.contains('?') { '&' } else { '?' };
+    format!("{}{}{}limit={}", base_url, path, sep, PAGE_LIMIT)
+}
+
+impl<T> RawPage<T> {
+    /// the page after this one, `None` on the last. `offset` counts the rows fetched ahead of
+    /// this page.
+    pub(crate) fn next_url(&self, base_url: &str, first: &str, offset: u64) -> Option<String> {
+        let fetched = offset + self.data.len() as u64;
+        match (&self.meta.next, self.meta.total_count) {
+            _ if self.data.is_empty() => None,
+            (Some(next), _) => Some(resolve(base_url, next)),
+            (None, Some(total)) if fetched < total => Some(format!("{}&offset={}", first, fetched)),
+            _ => None,
+        }
+    }
+    pub(crate) fn into_data(self) -> Vec<T> {
+        self.data
+    }
+}
+
+/// `next` links come back either absolute or as a path on the exchange's host
+fn resolve(base_url: &str, next: &str) -> String {
+    if next.starts_with("http://") || next.starts_with("https://") {
+        return next.to_string();
+    }
+    let host_end = base_url
+        .find("://")
+        .and_then(|i| base_url[i + 3..].find('/').map(|j| i + 3 + j))
+        .unwrap_or(base_url.len());
+    format!("{}{}", &base_url[..host_end], next)
+}
+
+/// a single page, sent like any other read so it's paced and retried
+struct PageRequest<'u, R>(&'u str, PhantomData<R>);
+
+impl<R> SendWithMngr for PageRequest<'_, R>
+where
+    RawPage<R>: DeserializeOwned,
+{
+    type OkType = RawPage<R>;
+    fn send_with_mngr(&self, mngr: &OrderMngr) -> Result<RawPage<R>, OrderError> {
+        let resp = mngr.get(self.0)?;
+        Ok(serde_json::from_reader(resp.into_reader())?)
+    }
+}
+
+impl<'m, 'a, R> Iterator for Paginated<'m, 'a, R>
+where
+    R: SanitizableMsg<'static>,
+    RawPage<R>: DeserializeOwned,
+{
+    type Item = Result<R::OUT, OrderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            let url = self.next.take()?;
+            if let Err(e) = self.fetch(&url) {
+                return Some(Err(e));
+            }
+        }
+        self.buf.pop_front().map(|r| Ok(r.sanitize()))
+    }
+}
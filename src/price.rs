@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::OrderValidationError;
+
+/// an exact price in cents, the unit the exchange quotes and takes prices in.
+/// (de)serializes as the bare number of cents.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Price(u64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+
+    pub const fn from_cents(cents: u64) -> Self {
+        Price(cents)
+    }
+    /// rounds to the nearest cent, negative prices clamp to zero
+    pub fn from_dollars(dollars: f64) -> Self {
+        Price((dollars * 100.0).round().max(0.0) as u64)
+    }
+    pub const fn cents(self) -> u64 {
+        self.0
+    }
+    /// lossy, for display and maths only. never build an order from this.
+    pub fn dollars(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    /// a zero increment accepts every price
+    pub fn is_multiple_of(self, increment: Price) -> bool {
+        increment.0 == 0 || self.0.is_multiple_of(increment.0)
+    }
+    /// largest multiple of `increment` at or below this price
+    pub fn floor_to(self, increment: Price) -> Price {
+        if increment.0 == 0 {
+            return self;
+        }
+        Price(self.0 / increment.0 * increment.0)
+    }
+    /// smallest multiple of `increment` at or above this price
+    pub fn ceil_to(self, increment: Price) -> Price {
+        if increment.0 == 0 {
+            return self;
+        }
+        Price(self.0.div_ceil(increment.0) * increment.0)
+    }
+    /// this price, if it's a multiple of `increment`
+    pub fn check_increment(self, increment: Price) -> Result<Price, OrderValidationError> {
+        if self.is_multiple_of(increment) {
+            return Ok(self);
+        }
+        Err(OrderValidationError::InvalidIncrement {
+            price: self,
+            min_increment: increment,
+        })
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.0 / 100, self.0 % 100)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePriceError(pub String);
+
+/// parses a dollar amount exactly, e.g. `"1.75"`, `"$12"` or `"0.5"`
+impl FromStr for Price {
+    type Err = ParsePriceError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParsePriceError(format!("not a price in dollars and cents: {:?}", s));
+        let trimmed = s.trim().trim_start_matches('$');
+        let (whole, frac) = trimmed.split_once('.').unwrap_or((trimmed, ""));
+        if whole.is_empty() && frac.is_empty() || frac.len() > 2 {
+            return Err(err());
+        }
+        if !whole
+            .chars()
+            .chain(frac.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err(err());
+        }
+        let whole: u64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| err())?
+        };
+        let frac: u64 = format!("{:0<2}", frac).parse().map_err(|_| err())?;
+        whole
+            .checked_mul(100)
+            .and_then(|c| c.checked_add(frac))
+            .map(Price)
+            .ok_or_else(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dollars_are_exact() {
+        assert_eq!(Price::from_dollars(1.75).cents(), 175);
+        assert_eq!(Price::from_dollars(0.29).cents(), 29);
+        assert_eq!(Price::from_dollars(-3.0), Price::ZERO);
+        assert_eq!("1.75".parse::<Price>().unwrap().cents(), 175);
+        assert_eq!("$12".parse::<Price>().unwrap().cents(), 1200);
+        assert_eq!("0.5".parse::<Price>().unwrap().cents(), 50);
+        assert_eq!(".05".parse::<Price>().unwrap().cents(), 5);
+        assert!("1.755".parse::<Price>().is_err());
+        assert!("1,75".parse::<Price>().is_err());
+        assert!(".".parse::<Price>().is_err());
+        assert_eq!(Price::from_cents(4705).to_string(), "47.05");
+    }
+
+    #[test]
+    fn increments() {
+        let tick = Price::from_cents(25);
+        let p = Price::from_cents(160);
+        assert!(!p.is_multiple_of(tick));
+        assert_eq!(p.floor_to(tick).cents(), 150);
+        assert_eq!(p.ceil_to(tick).cents(), 175);
+        assert_eq!(Price::from_cents(175).ceil_to(tick).cents(), 175);
+        assert!(matches!(
+            p.check_increment(tick),
+            Err(OrderValidationError::InvalidIncrement { .. })
+        ));
+        assert_eq!(
+            Price::from_cents(175)
+                .check_increment(tick)
+                .unwrap()
+                .cents(),
+            175
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! retrying what `OrderMngr` sends. reads and cancels are safe to repeat, so any transient
+//! failure is retried. an order or edit is only repeated once we know the first attempt
+//! never took effect, otherwise we could end up filled twice.
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::OrderError;
+use crate::limit::RequestKind;
+use crate::order::{
+    OpenOrders, Order, OrderHistory, OrderMngr, OrderRecord, OrderResponse, OrderType, SendWithMngr,
+};
+
+/// how `OrderMngr` retries a failed request. an order that fails ambiguously is only looked
+/// for, and then resent, once `OrderMngr::orders` has seen an action report on its contract.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// wait before the first retry, grown by `multiplier` after every failed one. a longer
+    /// `Retry-After` from the exchange wins.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: u32,
+    /// attempts in total, the first one included
+    pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2,
+            max_attempts: 3,
+        }
+    }
+}
+
+/// whether an attempt that failed ambiguously reached the exchange after all
+pub enum Landed<T> {
+    /// it did, this is what it would have returned
+    Yes(T),
+    No,
+    /// couldn't tell, so it mustn't be repeated
+    Unknown,
+}
+
+/// the exchange can't have acted on the request
+fn never_happened(e: &OrderError) -> bool {
+    matches!(
+        e,
+        OrderError::ConnectionFailed(_) | OrderError::RateLimited { .. }
+    )
+}
+
+/// the request may or may not have been acted on
+fn ambiguous(e: &OrderError) -> bool {
+    match e {
+        OrderError::Transport(_) => true,
+        OrderError::Exchange { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+impl<'a> OrderMngr<'a> {
+    /// sends `action`, retrying as the mngr's `RetryPolicy` allows
+    pub(crate) fn send_with_retry<T>(&self, action: &T) -> Result<T::OkType, OrderError>
+    where
+        T: SendWithMngr,
+    {
+        let mut out = self.send_once(action);
+        let Some(policy) = &self.retry else {
+            return out;
+        };
+
+        let mut backoff = policy.initial_backoff;
+        let mut maybe_landed = false;
+        for _ in 1..policy.max_attempts {
+            let e = match &out {
+                Ok(_) => return out,
+                Err(e) => e,
+            };
+            maybe_landed |= ambiguous(e);
+            let retry = match action.kind() {
+                RequestKind::Read | RequestKind::Cancel => never_happened(e) || ambiguous(e),
+                RequestKind::Order if never_happened(e) => true,
+                RequestKind::Order if ambiguous(e) => match action.landed(self) {
+                    Landed::Yes(found) => return Ok(found),
+                    Landed::No => true,
+                    Landed::Unknown => false,
+                },
+                RequestKind::Order => false,
+            };
+            if !retry {
+                return out;
+            }
+
+            let wait = match e {
+                OrderError::RateLimited {
+                    retry_after: Some(d),
+                } => (*d).max(backoff),
+                _ => backoff,
+            };
+            thread::sleep(wait);
+            backoff = (backoff * policy.multiplier).min(policy.max_backoff);
+            out = self.send_once(action);
+
+            // a cancel retried after an ambiguous failure finds nothing to cancel when the
+            // earlier attempt got there first
+            let gone = matches!(out, Err(OrderError::OrderNotFound(_)));
+            if gone && maybe_landed && action.kind() == RequestKind::Cancel {
+                if let Landed::Yes(done) = action.landed(self) {
+                    return Ok(done);
+                }
+            }
+        }
+        return out;
+    }
+}
+
+/// the exchange's copy of `ord`, if it has one nobody has accounted for yet. only records at
+/// or past `since`, the newest clock we've seen on the contract, count: anything older was
+/// there before the attempt. identical orders sent at the same moment can't be told apart,
+/// the first match is taken and claimed so another order in the batch can't take it too.
+fn find_order<'r>(
+    mngr: &OrderMngr,
+    ord: &Order,
+    since: u64,
+    records: &'r [OrderRecord],
+    claimed: &HashSet<String>,
+) -> Option<&'r OrderRecord> {
+    records.iter().find(|r| {
+        r.contract_id == ord.contract_id
+            && r.is_ask == ord.is_ask
+            && r.size == ord.size
+            && r.order_type == ord.order_type
+            && (ord.order_type == OrderType::Market || r.price == ord.price)
+            && r.clock >= since
+            && mngr.orders.get(&r.mid).is_none()
+            && !claimed.contains(&r.mid)
+    })
+}
+
+impl Order {
+    /// looks for the order in open orders and then in the order history, since it may have
+    /// filled straight away. without a clock from the contract's action reports there's no
+    /// telling our order from one that was already resting, so it's `Unknown`.
+    pub(crate) fn find_landed(&self, mngr: &OrderMngr) -> Landed<OrderResponse> {
+        let since = mngr.orders.latest_clock(self.contract_id);
+        if since == 0 {
+            return Landed::Unknown;
+        }
+        for records in [mngr.send_once(&OpenOrders), mngr.send_once(&OrderHistory)] {
+            match records {
+                Ok(records) => {
+                    let mut claimed = mngr.claimed.lock().unwrap();
+                    if let Some(r) = find_order(mngr, self, since, &records, &claimed) {
+                        claimed.insert(r.mid.clone());
+                        return Landed::Yes(OrderResponse {
+                            order_id: r.mid.clone(),
+                        });
+                    }
+                }
+                Err(_) => return Landed::Unknown,
+            }
+        }
+        Landed::No
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::order::Cancel;
+    use crate::price::Price;
+    use crate::ws::WebSocketMsgParser;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn quick() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn cancels_and_reads_are_retried() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let hits = hits.clone();
+            MockServer::start(move |_| match hits.fetch_add(1, Ordering::SeqCst) {
+                0 => (503, r#"{"error": "busy"}"#.to_string()),
+                _ => (200, r#"{"data": []}"#.to_string()),
+            })
+        };
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(quick()));
+
+        assert!(om.send_cancel(&Cancel::all()).is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(om.open_orders().unwrap().is_empty());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retried_cancels_of_a_gone_order_succeed() {
+        // the first DELETE cancels the order but its response is lost
+        let hits = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let hits = hits.clone();
+            MockServer::start(move |_| match hits.fetch_add(1, Ordering::SeqCst) {
+                0 => (503, r#"{"error": "busy"}"#.to_string()),
+                _ => (404, r#"{"error": "order not found"}"#.to_string()),
+            })
+        };
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(quick()));
+
+        assert_eq!(om.send_cancel(&Cancel::one("m".to_string(), 1)), Ok(()));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // without an ambiguous attempt before it, a missing order is still an error
+        let out = om.send_cancel(&Cancel::one("m".to_string(), 1));
+        assert!(matches!(out, Err(OrderError::OrderNotFound(_))));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn missing_endpoints_are_not_gone_orders() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let hits = hits.clone();
+            MockServer::start(move |_| match hits.fetch_add(1, Ordering::SeqCst) {
+                0 => (503, r#"{"error": "busy"}"#.to_string()),
+                _ => (404, r#"{"error": "not found"}"#.to_string()),
+            })
+        };
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(quick()));
+
+        // cancelling everything has no single order to have gone missing
+        let out = om.send_cancel(&Cancel::all());
+        assert!(matches!(out, Err(OrderError::Exchange { status: 404, .. })));
+        let out = om.collateral_balances();
+        assert!(matches!(out, Err(OrderError::Exchange { status: 404, .. })));
+    }
+
+    /// an action report on contract 1 at `clock`, for an order of someone else's
+    fn seen(om: &mut OrderMngr, clock: u64) {
+        om.orders.apply(
+            &mut WebSocketMsgParser::parse_text(&format!(
+                r#"{{"type": "action_report", "mid": "seen", "contract_id": 1, "status_type": 200, "is_ask": true, "price": 300, "size": 1, "open_size": 1, "clock": {clock}}}"#
+            ))
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn orders_are_confirmed_before_a_retry() {
+        // the first attempt lands but its response is lost
+        let server = MockServer::start(|req| {
+            match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") => (502, "bad gateway".to_string()),
+            ("GET", "/open-orders") => (
+                200,
+                r#"{"data": [{"mid": "landed", "contract_id": 1, "status_type": 200, "is_ask": false, "price": 175, "size": 2, "open_size": 2, "clock": 21}]}"#
+                    .to_string(),
+            ),
+            ("GET", "/order-history") => (200, r#"{"data": []}"#.to_string()),
+            _ => (404, "{}".to_string()),
+        }
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(quick()));
+        let posts = || {
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.method == "POST")
+                .count()
+        };
+
+        // with no clock to go on, "landed" may have been resting before we sent anything
+        let out = om.send_order(&Order::new(1, false, Price::from_cents(175), 2));
+        assert!(matches!(out, Err(OrderError::Exchange { status: 502, .. })));
+        assert_eq!(posts(), 1);
+
+        seen(&mut om, 20);
+        let out = om
+            .send_order(&Order::new(1, false, Price::from_cents(175), 2))
+            .unwrap();
+        assert_eq!(out.order_id, "landed");
+        assert!(om.orders.get("landed").is_some());
+        assert_eq!(posts(), 2);
+
+        // nothing matches this one, so it's resent until attempts run out
+        let out = om.send_order(&Order::new(1, false, Price::from_cents(150), 2));
+        assert!(matches!(out, Err(OrderError::Exchange { status: 502, .. })));
+        assert_eq!(posts(), 5);
+    }
+
+    #[test]
+    fn landed_orders_are_new_and_claimed_once() {
+        // both of a ladder's identical orders land but lose their responses. "old" was resting
+        // on the contract before we sent anything.
+        let server = MockServer::start(|req| {
+            match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") => (502, "bad gateway".to_string()),
+            ("GET", "/open-orders") => (
+                200,
+                r#"{"data": [
+                    {"mid": "old", "contract_id": 1, "status_type": 200, "is_ask": false, "price": 175, "size": 2, "open_size": 2, "clock": 5},
+                    {"mid": "new1", "contract_id": 1, "status_type": 200, "is_ask": false, "price": 175, "size": 2, "open_size": 2, "clock": 21},
+                    {"mid": "new2", "contract_id": 1, "status_type": 200, "is_ask": false, "price": 175, "size": 2, "open_size": 2, "clock": 22}
+                ]}"#
+                    .to_string(),
+            ),
+            ("GET", "/order-history") => (200, r#"{"data": []}"#.to_string()),
+            _ => (404, "{}".to_string()),
+        }
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+        om.retry_policy(Some(quick()));
+        seen(&mut om, 20);
+
+        let ord = Order::new(1, false, Price::from_cents(175), 2);
+        let out = om.send_orders(&[ord.clone(), ord]);
+        let mut mids: Vec<String> = out.into_iter().map(|r| r.unwrap().order_id).collect();
+        mids.sort();
+        assert_eq!(mids, ["new1", "new2"]);
+    }
+}
@@ -1,14 +1,30 @@
-use chrono::TimeZone;
 use chrono::{offset::Utc, DateTime};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::error::WebSocketError;
-use crate::ws::{RawMsg, SanitizableMsg};
+use crate::error::{TableError, WebSocketError};
+use crate::page::{first_page, RawPage};
+use crate::price::Price;
+use crate::ws::{BookTop, RawBookTop, RawMsg, SanitizableMsg};
 
 fn parse_ftx_datetime(dt: &str) -> DateTime<Utc> {
-    Utc.datetime_from_str(dt, "%Y-%m-%d %H:%M:%S%z").unwrap()
+    DateTime::parse_from_str(dt, "%Y-%m-%d %H:%M:%S%z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// serde `deserialize_with` for exchange timestamps, so a bad one is a parse error rather than
+/// a panic. rfc 3339 is accepted as well.
+pub(crate) fn deserialize_ftx_datetime<'de, D>(d: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(d)?;
+    DateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(&s))
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
 }
 
 fn years_til_strfdt(dt: &str) -> f64 {
@@ -54,6 +70,57 @@ impl ContractSpec {
             _ => None,
         }
     }
+
+    // common fields, whatever the derivative type
+    pub fn id(&self) -> u64 {
+        match self {
+            ContractSpec::Option(o) => o.id,
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => r.id,
+        }
+    }
+    pub fn label(&self) -> &str {
+        match self {
+            ContractSpec::Option(o) => &o.label,
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => {
+                &r.label
+            }
+        }
+    }
+    pub fn active(&self) -> bool {
+        match self {
+            ContractSpec::Option(o) => o.active,
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => {
+                r.active
+            }
+        }
+    }
+    /// `None` if the exchange sent a future or swap without a readable expiry
+    pub fn date_expires(&self) -> Option<DateTime<Utc>> {
+        match self {
+            ContractSpec::Option(o) => Some(o.date_expires),
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => {
+                DateTime::parse_from_str(&r.date_expires, "%Y-%m-%d %H:%M:%S%z")
+                    .ok()
+                    .map(|dt| dt.with_timezone(&Utc))
+            }
+        }
+    }
+    pub fn min_increment(&self) -> Price {
+        match self {
+            ContractSpec::Option(o) => o.min_increment,
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => {
+                Price::from_cents(r.min_increment as u64)
+            }
+        }
+    }
+    pub fn is_ecp_only(&self) -> bool {
+        match self {
+            ContractSpec::Option(o) => o.is_ecp_only,
+            ContractSpec::Future(FutureContractSpec(r)) | ContractSpec::Swap(SwapSpec(r)) => {
+                r.is_ecp_only
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -63,13 +130,13 @@ pub struct OptionContractSpec {
     pub label: String,
     // contract specs
     pub underlying: String,
-    pub strike_price: u64,
+    pub strike_price: Price,
     pub is_call: bool,
     pub tte: f64, // annualized
     pub open_interest: u32,
     // contract specs pt.2
     pub multiplier: f64,
-    pub min_increment: f64,
+    pub min_increment: Price,
     // auxilliary data
     pub active: bool,
     pub date_live: DateTime<Utc>,
@@ -81,10 +148,10 @@ pub struct OptionContractSpec {
 
 // build these below ones out as needed
 #[derive(Debug, Clone)]
-pub struct FutureContractSpec(RawContractSpec);
+pub struct FutureContractSpec(pub RawContractSpec);
 
 #[derive(Debug, Clone)]
-pub struct SwapSpec(RawContractSpec);
+pub struct SwapSpec(pub RawContractSpec);
 
 //
 // NON-SANITIZED TABLES
@@ -126,13 +193,13 @@ impl<'a> SanitizableMsg<'a> for RawContractSpecTable {
                     label: i.label,
 
                     underlying: i.underlying_asset,
-                    strike_price: (i.strike_price.unwrap() / 100) as u64,
+                    strike_price: Price::from_cents(i.strike_price.unwrap() as u64),
                     is_call: i.is_call.unwrap(),
                     tte: years_til_strfdt(&i.date_expires),
                     open_interest: i.open_interest.unwrap_or(0),
 
                     multiplier: i.multiplier as f64,
-                    min_increment: i.min_increment as f64 / 100.0,
+                    min_increment: Price::from_cents(i.min_increment as u64),
 
                     active: i.active,
                     date_live: parse_ftx_datetime(&i.date_live),
@@ -175,3 +242,284 @@ pub struct RawContractSpec {
     pub multiplier: u32,
     pub is_ecp_only: bool,
 }
+
+//
+// PUBLIC MARKET DATA
+//
+
+pub const PUBLIC_BASE_URL: &str = "https://api.ledgerx.com/trading";
+
+/// the exchange's unauthenticated market data endpoints: the public trade tape, daily contract
+/// summaries and historical book tops. every query takes an optional `[after, before)` window.
+pub struct MarketData<'a> {
+    base_url: &'a str,
+    agent: ureq::Agent,
+}
+
+impl<'a> MarketData<'a> {
+    pub fn new(base_url: &'a str) -> Self {
+        MarketData {
+            base_url,
+            agent: ureq::Agent::new(),
+        }
+    }
+    /// every public trade on the contract
+    pub fn trades(
+        &self,
+        contract_id: u64,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<PublicTrade>, TableError> {
+        let raw: Vec<RawPublicTrade> =
+            self.get(&format!("/contracts/{}/trades", contract_id), after, before)?;
+        Ok(raw.into_iter().map(|t| t.sanitize()).collect())
+    }
+    /// one summary per trading day
+    pub fn summaries(
+        &self,
+        contract_id: u64,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ContractSummary>, TableError> {
+        let raw: Vec<RawContractSummary> = self.get(
+            &format!("/contracts/{}/summaries", contract_id),
+            after,
+            before,
+        )?;
+        Ok(raw.into_iter().map(|t| t.sanitize()).collect())
+    }
+    pub fn book_tops(
+        &self,
+        contract_id: u64,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<HistoricalBookTop>, TableError> {
+        let raw: Vec<RawHistoricalBookTop> =
+            self.get(&format!("/book-tops/{}", contract_id), after, before)?;
+        Ok(raw.into_iter().map(|t| t.sanitize()).collect())
+    }
+
+    /// every row of the listing at `path`, following its pages to the end
+    fn get<T>(
+        &self,
+        path: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<T>, TableError>
+    where
+        RawPage<T>: serde::de::DeserializeOwned,
+    {
+        let mut window = Vec::new();
+        if let Some(t) = after {
+            window.push(format!("after_ts={}", t.timestamp()));
+        }
+        if let Some(t) = before {
+            window.push(format!("before_ts={}", t.timestamp()));
+        }
+        let path = match window.is_empty() {
+            true => path.to_string(),
+            false => format!("{}?{}", path, window.join("&")),
+        };
+
+        let first = first_page(self.base_url, &path);
+        let mut next = Some(first.clone());
+        let mut out = Vec::new();
+        while let Some(url) = next {
+            let resp = self
+                .agent
+                .get(&url)
+                .set("Accept", "application/json")
+                .call()?;
+            let page: RawPage<T> = serde_json::from_reader(resp.into_reader())?;
+            next = page.next_url(self.base_url, &first, out.len() as u64);
+            out.extend(page.into_data());
+        }
+        Ok(out)
+    }
+}
+
+impl Default for MarketData<'_> {
+    fn default() -> Self {
+        MarketData::new(PUBLIC_BASE_URL)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicTrade {
+    pub contract_id: u64,
+    pub price: Price,
+    pub size: u64,
+    /// whether the aggressor was selling
+    pub is_ask: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawPublicTrade {
+    contract_id: u64,
+    price: u64,
+    size: u64,
+    #[serde(default)]
+    is_ask: bool,
+    #[serde(deserialize_with = "deserialize_ftx_datetime")]
+    created_time: DateTime<Utc>,
+}
+
+impl<'a> SanitizableMsg<'a> for RawPublicTrade {
+    type OUT = PublicTrade;
+    fn sanitize(self) -> Self::OUT {
+        PublicTrade {
+            contract_id: self.contract_id,
+            price: Price::from_cents(self.price),
+            size: self.size,
+            is_ask: self.is_ask,
+            timestamp: self.created_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractSummary {
+    pub contract_id: u64,
+    pub date: DateTime<Utc>,
+    /// contracts traded that day
+    pub volume: u64,
+    /// `None` if nothing has traded yet
+    pub last_trade: Option<Price>,
+    pub open_interest: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawContractSummary {
+    contract_id: u64,
+    #[serde(deserialize_with = "deserialize_ftx_datetime")]
+    date: DateTime<Utc>,
+    #[serde(default)]
+    volume: u64,
+    last_trade_price: Option<u64>,
+    #[serde(default)]
+    open_interest: u64,
+}
+
+impl<'a> SanitizableMsg<'a> for RawContractSummary {
+    type OUT = ContractSummary;
+    fn sanitize(self) -> Self::OUT {
+        ContractSummary {
+            contract_id: self.contract_id,
+            date: self.date,
+            volume: self.volume,
+            last_trade: self.last_trade_price.map(Price::from_cents),
+            open_interest: self.open_interest,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HistoricalBookTop {
+    pub timestamp: DateTime<Utc>,
+    pub top: BookTop,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawHistoricalBookTop {
+    #[serde(deserialize_with = "deserialize_ftx_datetime")]
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    top: RawBookTop,
+}
+
+impl<'a> SanitizableMsg<'a> for RawHistoricalBookTop {
+    type OUT = HistoricalBookTop;
+    fn sanitize(self) -> Self::OUT {
+        HistoricalBookTop {
+            timestamp: self.timestamp,
+            top: self.top.sanitize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    #[test]
+    fn unreadable_expiries_are_none() {
+        let contract = |id: u64, kind: &str, expires: &str| {
+            format!(
+                r#"{{"id": {id}, "label": "CBTC-{id}", "is_call": null, "active": true, "strike_price": null, "min_increment": 100, "date_live": "2021-01-01 21:00:00+0000", "date_expires": "{expires}", "date_exercise": null, "underlying_asset": "CBTC", "collateral_asset": "USD", "derivative_type": "{kind}", "open_interest": null, "is_next_day": true, "multiplier": 1, "is_ecp_only": false}}"#
+            )
+        };
+        let raw = format!(
+            r#"{{"data": [{}, {}]}}"#,
+            contract(1, "future_contract", "2099-12-31 21:00:00+0000"),
+            contract(2, "day_ahead_swap", ""),
+        );
+        let specs = RawContractSpecTable::parse(&raw).unwrap().sanitize();
+
+        let expires = specs.id_table[&1].date_expires().unwrap();
+        assert_eq!(expires.to_rfc3339(), "2099-12-31T21:00:00+00:00");
+        assert_eq!(specs.id_table[&2].date_expires(), None);
+    }
+
+    #[test]
+    fn market_data_queries() {
+        let server = MockServer::start(|req| {
+            match req.url.as_str() {
+            "/contracts/1/trades?after_ts=1646092800&before_ts=1646179200&limit=200" => (
+                200,
+                r#"{"data": [{"contract_id": 1, "price": 175, "size": 3, "is_ask": true, "created_time": "2022-03-01 15:04:05+0000"}]}"#
+                    .to_string(),
+            ),
+            // two pages, the second found by cursor
+            "/contracts/1/summaries?limit=200" => (
+                200,
+                r#"{"meta": {"next": "/contracts/1/summaries?after=1&limit=200"}, "data": [{"contract_id": 1, "date": "2022-03-01T00:00:00Z", "volume": 40, "last_trade_price": 175, "open_interest": 12}]}"#
+                    .to_string(),
+            ),
+            "/contracts/1/summaries?after=1&limit=200" => (
+                200,
+                r#"{"meta": {"next": null}, "data": [{"contract_id": 1, "date": "2022-03-02T00:00:00Z", "last_trade_price": null}]}"#
+                    .to_string(),
+            ),
+            // and by offset
+            "/book-tops/1?limit=200" => (
+                200,
+                r#"{"meta": {"total_count": 2}, "data": [{"timestamp": "2022-03-01 15:04:05+0000", "bid": 150, "bid_size": 2, "ask": 175, "ask_size": 1, "contract_id": 1, "contract_type": 0, "clock": 41}]}"#
+                    .to_string(),
+            ),
+            "/book-tops/1?limit=200&offset=1" => (
+                200,
+                r#"{"meta": {"total_count": 2}, "data": [{"timestamp": "2022-03-01 15:04:06+0000", "bid": 150, "bid_size": 1, "ask": 175, "ask_size": 1, "contract_id": 1, "contract_type": 0, "clock": 42}]}"#
+                    .to_string(),
+            ),
+            _ => (404, r#"{"error": "not found"}"#.to_string()),
+        }
+        });
+        let md = MarketData::new(&server.url);
+        let day = |d: &str| Some(DateTime::parse_from_rfc3339(d).unwrap().with_timezone(&Utc));
+
+        let trades = md
+            .trades(1, day("2022-03-01T00:00:00Z"), day("2022-03-02T00:00:00Z"))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Price::from_cents(175));
+        assert!(trades[0].is_ask);
+
+        let sums = md.summaries(1, None, None).unwrap();
+        assert_eq!(sums.len(), 2);
+        assert_eq!(sums[0].volume, 40);
+        assert_eq!(sums[1].last_trade, None);
+        assert_eq!(sums[1].volume, 0);
+
+        let tops = md.book_tops(1, None, None).unwrap();
+        assert_eq!(tops[0].top.bid, Price::from_cents(150));
+        assert_eq!(tops[0].top.clock, 41);
+        assert_eq!(tops[1].top.clock, 42);
+
+        assert!(matches!(
+            md.trades(2, None, None),
+            Err(TableError::ClientError(404, _))
+        ));
+    }
+}
@@ -0,0 +1,420 @@
+//! one authoritative `OrderState` per order id, merged from what we sent, the WebSocket
+//! action reports and periodic REST snapshots
+
+use std::collections::HashMap;
+
+use crate::error::OrderError;
+use crate::order::{Cancel, Order, OrderEdit, OrderMngr, OrderRecord, OrderResponse};
+use crate::price::Price;
+use crate::ws::{ActionReport, ActionStatus, WebSocketMsg};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderState {
+    pub mid: String,
+    pub contract_id: u64,
+    pub is_ask: bool,
+    pub price: Price,
+    /// original size, or the new one after an edit
+    pub size: u64,
+    pub open_size: u64,
+    pub filled_size: u64,
+    /// price of the latest fill, zero until something fills
+    pub filled_price: Price,
+    pub status: ActionStatus,
+    /// clock of the last update applied, older ones are ignored
+    pub clock: u64,
+    /// the `Order::client_tag` it was sent with, `None` for orders sent elsewhere
+    pub client_tag: Option<String>,
+}
+
+impl OrderState {
+    /// resting on the book with something left to fill
+    pub fn is_open(&self) -> bool {
+        self.open_size > 0
+            && matches!(
+                self.status,
+                ActionStatus::Inserted | ActionStatus::PartiallyFilled | ActionStatus::Edited
+            )
+    }
+    fn from_record(r: &OrderRecord) -> Self {
+        OrderState {
+            mid: r.mid.clone(),
+            contract_id: r.contract_id,
+            is_ask: r.is_ask,
+            price: r.price,
+            size: r.size,
+            open_size: r.open_size,
+            filled_size: r.filled_size,
+            filled_price: r.filled_price,
+            status: r.status,
+            clock: r.clock,
+            client_tag: None,
+        }
+    }
+}
+
+/// what `OrderTracker::reconcile` found out of line with the exchange. the exchange's view
+/// has already been adopted by the time these are returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    /// we had it open but the exchange doesn't, look it up with `order_status` and `resolve` it
+    MissingOnExchange(OrderState),
+    /// the exchange has an open order we weren't tracking, e.g. sent by another process
+    Untracked(OrderRecord),
+    /// both sides have it but disagree on price, size or what's left open
+    Mismatch {
+        ours: OrderState,
+        theirs: OrderRecord,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct OrderTracker {
+    orders: HashMap<String, OrderState>,
+}
+
+impl OrderTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, mid: &str) -> Option<&OrderState> {
+        self.orders.get(mid)
+    }
+    pub fn open(&self) -> impl Iterator<Item = &OrderState> {
+        self.orders.values().filter(|o| o.is_open())
+    }
+    pub fn all(&self) -> impl Iterator<Item = &OrderState> {
+        self.orders.values()
+    }
+    /// every order sent with `Order::client_tag` set to `tag`
+    pub fn tagged<'t>(&'t self, tag: &'t str) -> impl Iterator<Item = &'t OrderState> {
+        self.orders
+            .values()
+            .filter(move |o| o.client_tag.as_deref() == Some(tag))
+    }
+    /// the newest clock any action report has shown us for `contract_id`, 0 if none has
+    pub fn latest_clock(&self, contract_id: u64) -> u64 {
+        self.orders
+            .values()
+            .filter(|o| o.contract_id == contract_id)
+            .map(|o| o.clock)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// starts tracking an order the exchange just accepted
+    pub fn track(&mut self, resp: &OrderResponse, ord: &Order) {
+        let state = self
+            .orders
+            .entry(resp.order_id.clone())
+            .or_insert_with(|| OrderState {
+                mid: resp.order_id.clone(),
+                contract_id: ord.contract_id,
+                is_ask: ord.is_ask,
+                price: ord.price,
+                size: ord.size,
+                open_size: ord.size,
+                filled_size: 0,
+                filled_price: Price::ZERO,
+                status: ActionStatus::Inserted,
+                clock: 0,
+                client_tag: None,
+            });
+        // an action report can beat the response here, the tag only comes with the order
+        state.client_tag = ord.client_tag.clone();
+    }
+
+    /// applies an edit the exchange accepted to an order that's still open
+    pub fn edited(&mut self, edit: &OrderEdit) {
+        if let Some(state) = self.orders.get_mut(&edit.order_id) {
+            if state.is_open() {
+                state.price = edit.price;
+                state.size = edit.size;
+                state.open_size = edit.size.saturating_sub(state.filled_size);
+                state.status = ActionStatus::Edited;
+            }
+        }
+    }
+    /// applies a cancel the exchange accepted, `Cancel::all` closes every open order
+    pub fn cancelled(&mut self, cancel: &Cancel) {
+        for state in self.orders.values_mut() {
+            let hit = match &cancel.0 {
+                Some((mid, _)) => state.mid == *mid,
+                None => state.is_open(),
+            };
+            if hit {
+                state.open_size = 0;
+                state.status = ActionStatus::Cancelled;
+            }
+        }
+    }
+
+    /// advances on an action report, anything else is ignored. the report is tagged with the
+    /// order's `client_tag` on the way through. returns the updated state.
+    pub fn apply(&mut self, msg: &mut WebSocketMsg) -> Option<&OrderState> {
+        match msg {
+            WebSocketMsg::ActionReport(ar) => self.apply_action_report(ar),
+            _ => None,
+        }
+    }
+    pub fn apply_action_report(&mut self, ar: &mut ActionReport) -> Option<&OrderState> {
+        let state = self
+            .orders
+            .entry(ar.mid.clone())
+            .or_insert_with(|| OrderState {
+                mid: ar.mid.clone(),
+                contract_id: ar.contract_id,
+                is_ask: ar.is_ask,
+                price: ar.price,
+                size: ar.size,
+                open_size: ar.size,
+                filled_size: 0,
+                filled_price: Price::ZERO,
+                status: ActionStatus::Inserted,
+                clock: 0,
+                client_tag: None,
+            });
+        ar.client_tag = state.client_tag.clone();
+        if ar.clock < state.clock {
+            return Some(state);
+        }
+        state.clock = ar.clock;
+        state.status = ar.status;
+
+        match ar.status {
+            ActionStatus::Filled | ActionStatus::PartiallyFilled => {
+                state.open_size = ar.open_size;
+                state.filled_size = state.size.saturating_sub(ar.open_size);
+                state.filled_price = ar.filled_price;
+            }
+            ActionStatus::Edited => {
+                state.price = ar.price;
+                state.size = ar.size;
+                state.open_size = ar.open_size;
+            }
+            ActionStatus::Cancelled | ActionStatus::NotFilled | ActionStatus::Rejected(_) => {
+                state.open_size = 0;
+            }
+            ActionStatus::Inserted | ActionStatus::Other(_) => {}
+        }
+        Some(state)
+    }
+
+    /// applies a single record from `order_status`, unless we've already seen something newer
+    pub fn resolve(&mut self, record: &OrderRecord) {
+        match self.orders.get_mut(&record.mid) {
+            Some(state) if record.clock < state.clock => {}
+            Some(state) => {
+                *state = OrderState {
+                    client_tag: state.client_tag.take(),
+                    ..OrderState::from_record(record)
+                }
+            }
+            None => {
+                self.orders
+                    .insert(record.mid.clone(), OrderState::from_record(record));
+            }
+        }
+    }
+
+    /// compares everything we think is open against the exchange's `open_orders`, adopts the
+    /// exchange's view and flags every difference
+    pub fn reconcile(&mut self, open: &[OrderRecord]) -> Vec<Discrepancy> {
+        let mut out = Vec::new();
+
+        for ours in self.open() {
+            if !open.iter().any(|r| r.mid == ours.mid) {
+                out.push(Discrepancy::MissingOnExchange(ours.clone()));
+            }
+        }
+        for theirs in open {
+            match self.orders.get(&theirs.mid) {
+                None => out.push(Discrepancy::Untracked(theirs.clone())),
+                // a snapshot older than our last action report can't tell us anything new
+                Some(ours) if theirs.clock < ours.clock => continue,
+                Some(ours)
+                    if ours.price != theirs.price
+                        || ours.size != theirs.size
+                        || ours.open_size != theirs.open_size =>
+                {
+                    out.push(Discrepancy::Mismatch {
+                        ours: ours.clone(),
+                        theirs: theirs.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+            self.resolve(theirs);
+        }
+        return out;
+    }
+}
+
+impl<'a> OrderMngr<'a> {
+    /// live state of every order sent with `Order::client_tag` set to `tag`
+    pub fn orders_tagged<'t>(&'t self, tag: &'t str) -> impl Iterator<Item = &'t OrderState> {
+        self.orders.tagged(tag)
+    }
+    /// the accepted orders in `order_history` sent with `tag`, oldest first
+    pub fn history_tagged<'t>(
+        &'t self,
+        tag: &'t str,
+    ) -> impl Iterator<Item = &'t (OrderResponse, Order)> {
+        self.order_history
+            .iter()
+            .filter(move |(_, o)| o.client_tag.as_deref() == Some(tag))
+    }
+    /// reconciles `orders` against `open_orders`, then looks up every order the exchange no
+    /// longer has open so the tracker learns how it ended. an order whose lookup fails stays
+    /// open in the tracker and is flagged again next time.
+    pub fn reconcile(&mut self) -> Result<Vec<Discrepancy>, OrderError> {
+        let open = self.open_orders()?;
+        let out = self.orders.reconcile(&open);
+        for d in &out {
+            if let Discrepancy::MissingOnExchange(ours) = d {
+                if let Ok(record) = self.order_status(&ours.mid) {
+                    self.orders.resolve(&record);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::ws::WebSocketMsgParser;
+
+    fn report(mid: &str, status_type: u64, size: u64, open_size: u64, clock: u64) -> WebSocketMsg {
+        WebSocketMsgParser::parse_text(&format!(
+            r#"{{"type": "action_report", "mid": "{mid}", "contract_id": 1, "status_type": {status_type}, "is_ask": false, "price": 175, "size": {size}, "filled_price": 175, "filled_size": 1, "open_size": {open_size}, "clock": {clock}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn action_reports_advance_state() {
+        let mut t = OrderTracker::new();
+        let ord = Order::new(1, false, Price::from_cents(175), 4);
+        t.track(
+            &OrderResponse {
+                order_id: "a".to_string(),
+            },
+            &ord,
+        );
+
+        t.apply(&mut report("a", 201, 4, 3, 10));
+        let a = t.get("a").unwrap();
+        assert_eq!(a.status, ActionStatus::PartiallyFilled);
+        assert_eq!((a.open_size, a.filled_size), (3, 1));
+        assert!(a.is_open());
+
+        // stale reports don't roll anything back
+        t.apply(&mut report("a", 200, 4, 4, 9));
+        assert_eq!(t.get("a").unwrap().open_size, 3);
+
+        t.apply(&mut report("a", 203, 4, 0, 11));
+        assert!(!t.get("a").unwrap().is_open());
+        assert_eq!(t.open().count(), 0);
+    }
+
+    #[test]
+    fn reconciles_against_rest() {
+        let server = MockServer::start(|req| {
+            match req.url.as_str() {
+            "/open-orders" => (
+                200,
+                r#"{"data": [
+                    {"mid": "b", "contract_id": 1, "status_type": 200, "is_ask": true, "price": 200, "size": 2, "open_size": 1, "clock": 20},
+                    {"mid": "c", "contract_id": 1, "status_type": 200, "is_ask": true, "price": 225, "size": 1, "open_size": 1, "clock": 21}
+                ]}"#
+                    .to_string(),
+            ),
+            "/orders/a" => (
+                200,
+                r#"{"data": {"mid": "a", "contract_id": 1, "status_type": 201, "is_ask": false, "price": 175, "size": 4, "filled_price": 175, "filled_size": 4, "open_size": 0, "clock": 22}}"#
+                    .to_string(),
+            ),
+            _ => (404, "{}".to_string()),
+        }
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+        // "d" can't be looked up, which mustn't lose the rest
+        let tracked = [
+            ("a", false, 175, 4),
+            ("b", true, 200, 2),
+            ("d", false, 150, 1),
+        ];
+        for (mid, is_ask, cents, size) in tracked {
+            om.orders.track(
+                &OrderResponse {
+                    order_id: mid.to_string(),
+                },
+                &Order::new(1, is_ask, Price::from_cents(cents), size),
+            );
+        }
+
+        let mut out = om.reconcile().unwrap();
+        out.sort_by_key(|d| format!("{:?}", d));
+        assert_eq!(out.len(), 4, "{:?}", out);
+        assert!(matches!(&out[0], Discrepancy::Mismatch { ours, .. } if ours.mid == "b"));
+        assert!(matches!(&out[1], Discrepancy::MissingOnExchange(o) if o.mid == "a"));
+        assert!(matches!(&out[2], Discrepancy::MissingOnExchange(o) if o.mid == "d"));
+        assert!(matches!(&out[3], Discrepancy::Untracked(r) if r.mid == "c"));
+
+        let t = &om.orders;
+        assert_eq!(t.get("a").unwrap().status, ActionStatus::Filled);
+        assert_eq!(t.get("b").unwrap().open_size, 1);
+        assert!(t.get("c").unwrap().is_open());
+        assert!(t.get("d").unwrap().is_open());
+        let out = om.reconcile().unwrap();
+        assert!(matches!(&out[..], [Discrepancy::MissingOnExchange(o)] if o.mid == "d"));
+    }
+
+    #[test]
+    fn client_tags_follow_orders() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.url.as_str()) {
+            ("POST", "/orders") if req.body.contains(r#""size":2"#) => {
+                (200, r#"{"mid": "m2"}"#.to_string())
+            }
+            ("POST", "/orders") => (200, r#"{"mid": "m1"}"#.to_string()),
+            ("DELETE", _) => (200, "{}".to_string()),
+            _ => (404, "{}".to_string()),
+        });
+        let mut om = OrderMngr::new(&server.url, "key");
+
+        let mut ord = Order::new(1, false, Price::from_cents(175), 3);
+        ord.client_tag("skew");
+        om.send_order(&ord).unwrap();
+        om.send_order(&Order::new(1, false, Price::from_cents(150), 2))
+            .unwrap();
+
+        // the tag is client-side only
+        assert!(!server.requests()[0].body.contains("skew"));
+
+        let mut fill = report("m1", 201, 3, 2, 5);
+        om.orders.apply(&mut fill);
+        assert!(matches!(
+            &fill,
+            WebSocketMsg::ActionReport(ar) if ar.client_tag.as_deref() == Some("skew")
+        ));
+        let tagged: Vec<&OrderState> = om.orders_tagged("skew").collect();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].mid, "m1");
+        assert_eq!(tagged[0].status, ActionStatus::PartiallyFilled);
+        assert_eq!(om.history_tagged("skew").count(), 1);
+
+        let out = om.cancel_tagged("skew");
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].0, "m1");
+        let deletes: Vec<String> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "DELETE")
+            .map(|r| r.url)
+            .collect();
+        assert_eq!(deletes, ["/orders/m1"]);
+    }
+}
@@ -1,44 +1,272 @@
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 use websocket::client::sync::Client;
-use websocket::stream::sync::NetworkStream;
+use websocket::stream::sync::{AsTcpStream, NetworkStream};
 use websocket::ClientBuilder;
 use websocket::OwnedMessage;
 
+use crate::account::{Fill, RawFill};
 use crate::error::WebSocketError;
+use crate::price::Price;
 
 pub struct WebSocketClient<'a> {
     // config
     endpoint: &'a str,
+    api_key: Option<&'a str>,
+    reconnect: Option<ReconnectPolicy>,
+    // consecutive failed reconnect attempts
     exhaustion_counter: u64,
 
     // state
     client: Client<Box<dyn NetworkStream + Send>>,
-    last_clock: u64,
+    last_clock: ClockTracker,
+    authenticated: bool,
+    closed: bool,
+    session: Option<SessionMeta>,
+    // (run_id, interval_ms) from the last heartbeat
+    heartbeat: Option<(u64, u64)>,
+    // frames held back while a `Resync` is handed out first
+    pending: VecDeque<WebSocketMsg>,
+}
+
+/// how `WebSocketClient` recovers from a dropped or silent connection
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// wait before the first attempt, grown by `multiplier` after every failed one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: u32,
+    /// consecutive failed attempts before giving up, `None` retries forever
+    pub max_attempts: Option<u64>,
+    /// heartbeat intervals that may pass without any frame before the connection is
+    /// considered dead
+    pub missed_heartbeats: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2,
+            max_attempts: Some(10),
+            missed_heartbeats: 3,
+        }
+    }
 }
 
 impl<'a> WebSocketClient<'a> {
+    /// opens an unauthenticated feed, only public channels (book tops, heartbeats) are sent
     pub fn connect(endpoint: &'a str) -> Result<Self, WebSocketError> {
-        let client = ClientBuilder::new(endpoint)?.connect(None)?;
+        Self::open(endpoint, None)
+    }
+    /// opens a feed authenticated with the JWT `api_key` and blocks until the exchange
+    /// acknowledges it. private channels (positions, balances) are only sent on these.
+    pub fn connect_authenticated(
+        endpoint: &'a str,
+        api_key: &'a str,
+    ) -> Result<Self, WebSocketError> {
+        let mut out = Self::open(endpoint, Some(api_key))?;
+        loop {
+            match out.yield_msg()? {
+                WebSocketMsg::AuthSuccess => return Ok(out),
+                WebSocketMsg::UnAuthSuccess => {
+                    return Err(WebSocketError::AuthFailure(
+                        "exchange did not accept the api key".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+    fn open(endpoint: &'a str, api_key: Option<&'a str>) -> Result<Self, WebSocketError> {
+        let client = Self::dial(endpoint, api_key)?;
         Ok(WebSocketClient {
             endpoint,
+            api_key,
+            reconnect: Some(ReconnectPolicy::default()),
             exhaustion_counter: 0,
             client,
-            last_clock: 0,
+            last_clock: ClockTracker::default(),
+            authenticated: false,
+            closed: false,
+            session: None,
+            heartbeat: None,
+            pending: VecDeque::new(),
         })
     }
+    fn dial(
+        endpoint: &str,
+        api_key: Option<&str>,
+    ) -> Result<Client<Box<dyn NetworkStream + Send>>, WebSocketError> {
+        let url = match api_key {
+            Some(key) => format!("{}?token={}", endpoint, key),
+            None => endpoint.to_string(),
+        };
+        Ok(ClientBuilder::new(&url)?.connect(None)?)
+    }
+    /// replaces the reconnect behaviour, `None` surfaces connection errors to the caller and
+    /// stops watching for missed heartbeats
+    pub fn reconnect_policy(
+        &mut self,
+        policy: Option<ReconnectPolicy>,
+    ) -> Result<(), WebSocketError> {
+        self.reconnect = policy;
+        match (&self.reconnect, self.heartbeat) {
+            (Some(policy), Some((_, interval_ms))) => self.arm_read_timeout(interval_ms, policy),
+            (Some(_), None) => Ok(()),
+            (None, _) => self.set_read_timeout(None),
+        }
+    }
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+    /// the `meta` message the exchange sent for this connection, if it has arrived yet
+    pub fn session(&self) -> Option<&SessionMeta> {
+        self.session.as_ref()
+    }
+    pub fn session_id(&self) -> Option<&str> {
+        self.session.as_ref().map(|m| m.session_id.as_str())
+    }
+    /// last `clock` seen on a contract's book tops and action reports
+    pub fn last_clock(&self, contract_id: u64) -> Option<u64> {
+        self.last_clock.get(contract_id)
+    }
+    /// blocks until the next message. a `Resync` is yielded ahead of any book top or action
+    /// report whose clock is behind the last one seen on that contract.
+    ///
+    /// if the connection drops, goes quiet for too many heartbeat intervals, or the
+    /// exchange's `run_id` changes, the feed is reopened according to the `ReconnectPolicy`
+    /// and `Reconnected` is yielded in place of a frame.
     pub fn yield_msg(&mut self) -> Result<WebSocketMsg, WebSocketError> {
-        let web_msg = self.client.recv_message()?;
-        
-        self.respond_if_ping(WebSocketMsgParser::parse(&web_msg))
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        if self.closed {
+            return Err(WebSocketError::Closed);
+        }
+        let web_msg = match self.client.recv_message() {
+            Ok(m) => m,
+            Err(e) => return self.reconnect(format!("receiving failed: {}", e)),
+        };
+
+        let msg = self.respond_if_ping(WebSocketMsgParser::parse(&web_msg))?;
+        match &msg {
+            WebSocketMsg::AuthSuccess => self.authenticated = true,
+            WebSocketMsg::Meta(meta) => self.session = Some(meta.clone()),
+            WebSocketMsg::Closed { .. } => self.teardown(),
+            WebSocketMsg::HeartBeat(hb) => {
+                if let Some((run_id, _)) = self.heartbeat {
+                    if run_id != hb.run_id {
+                        return self
+                            .reconnect(format!("run_id changed from {} to {}", run_id, hb.run_id));
+                    }
+                }
+                self.watch_heartbeat(hb)?;
+            }
+            _ => {}
+        }
+        if let Some(resync) = self.last_clock.check(&msg) {
+            self.pending.push_back(msg);
+            return Ok(resync);
+        }
+        Ok(msg)
+    }
+
+    /// answers the server's close frame and drops the connection, a graceful close is
+    /// never reconnected
+    fn teardown(&mut self) {
+        let _ = self.client.send_message(&OwnedMessage::Close(None));
+        let _ = self.client.shutdown();
+        self.closed = true;
+        self.authenticated = false;
+    }
+
+    /// arms the read timeout so a silent connection errors out instead of blocking forever
+    fn watch_heartbeat(&mut self, hb: &RawHeartbeat) -> Result<(), WebSocketError> {
+        let changed = self.heartbeat.map(|(_, i)| i) != Some(hb.interval_ms);
+        self.heartbeat = Some((hb.run_id, hb.interval_ms));
+        if let (true, Some(policy)) = (changed, &self.reconnect) {
+            self.arm_read_timeout(hb.interval_ms, policy)?;
+        }
+        Ok(())
+    }
+    fn arm_read_timeout(
+        &self,
+        interval_ms: u64,
+        policy: &ReconnectPolicy,
+    ) -> Result<(), WebSocketError> {
+        let timeout = interval_ms * policy.missed_heartbeats.max(1) as u64;
+        self.set_read_timeout(Some(Duration::from_millis(timeout.max(1))))
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), WebSocketError> {
+        self.client
+            .stream_ref()
+            .as_tcp()
+            .set_read_timeout(timeout)
+            .map_err(|e| WebSocketError::ConnectionError(e.to_string()))
     }
 
-    fn respond_if_ping(&mut self, msg: Result<WebSocketMsg, WebSocketError>) -> Result<WebSocketMsg, WebSocketError> {
-        if let Ok(inner) = &msg {
-            if let WebSocketMsg::Ping(data) = inner {
-                // println!("Got Ping:\t{:?}", data);
-                self.client.send_message(&websocket::OwnedMessage::Pong(data.to_owned()))?;
-                // println!("Sent Pong:\t{:?}", data);
+    /// reopens the feed with exponential backoff. the exchange subscribes a connection to
+    /// every channel its token allows, so redialing is all re-subscribing takes.
+    fn reconnect(&mut self, reason: String) -> Result<WebSocketMsg, WebSocketError> {
+        let policy = match &self.reconnect {
+            Some(p) => p.clone(),
+            None => return Err(WebSocketError::ConnectionError(reason)),
+        };
+        let _ = self.client.shutdown();
+
+        let mut backoff = policy.initial_backoff;
+        let client = loop {
+            if let Some(max) = policy.max_attempts {
+                if self.exhaustion_counter >= max {
+                    return Err(WebSocketError::ReconnectExhausted(
+                        reason,
+                        self.exhaustion_counter,
+                    ));
+                }
+            }
+            self.exhaustion_counter += 1;
+            thread::sleep(backoff);
+            match Self::dial(self.endpoint, self.api_key) {
+                Ok(c) => break c,
+                Err(_) => backoff = (backoff * policy.multiplier).min(policy.max_backoff),
             }
+        };
+
+        let attempts = self.exhaustion_counter;
+        self.exhaustion_counter = 0;
+        self.client = client;
+        // the new connection goes quiet just like the old one could, don't wait on its first
+        // heartbeat to start watching
+        if let Some((_, interval_ms)) = self.heartbeat {
+            self.arm_read_timeout(interval_ms, &policy)?;
+        }
+        self.authenticated = false;
+        self.session = None;
+        self.heartbeat = None;
+        self.pending.clear();
+        // anything sequenced on the old connection can't be trusted to line up
+        self.last_clock.reset();
+
+        Ok(WebSocketMsg::Reconnected { attempts, reason })
+    }
+
+    /// a pong that can't be sent means the connection is gone, it's reopened like a failed read
+    fn respond_if_ping(
+        &mut self,
+        msg: Result<WebSocketMsg, WebSocketError>,
+    ) -> Result<WebSocketMsg, WebSocketError> {
+        if let Ok(WebSocketMsg::Ping(data)) = &msg {
+            // println!("Got Ping:\t{:?}", data);
+            let pong = OwnedMessage::Pong(data.to_owned());
+            if let Err(e) = self.client.send_message(&pong) {
+                return self.reconnect(format!("answering a ping failed: {}", e));
+            }
+            // println!("Sent Pong:\t{:?}", data);
         }
         return msg;
     }
@@ -51,7 +279,74 @@ pub enum WebSocketMsg {
     BookTop(BookTop),
     HeartBeat(RawHeartbeat),
     UnAuthSuccess,
-    SessionID(String),
+    AuthSuccess,
+    Meta(SessionMeta),
+    // private channels, only sent on authenticated feeds
+    Positions(Vec<PositionUpdate>),
+    Collateral(CollateralBalances),
+    /// one of our orders traded, the same record `OrderMngr::fills` pages through
+    Fill(Fill),
+    ActionReport(ActionReport),
+    /// the feed was reopened after `attempts` tries, every contract's clock starts over
+    Reconnected {
+        attempts: u64,
+        reason: String,
+    },
+    /// the server closed the connection, the client is torn down and every later
+    /// `yield_msg` errors with `WebSocketError::Closed`
+    Closed {
+        code: Option<u16>,
+        reason: String,
+    },
+    /// a text frame with a `type` we don't model, passed through untouched
+    Unknown {
+        type_name: String,
+        raw: String,
+    },
+    /// frames for the contract arrived out of order (`clock < last_clock`), anything built
+    /// from the stream should be reloaded. a gap isn't one: the clock also moves on for
+    /// changes the public feed never sends, like those below the top of the book.
+    Resync {
+        contract_id: u64,
+        last_clock: u64,
+        clock: u64,
+    },
+}
+
+/// per-contract `clock` bookkeeping used to spot missed or reordered frames
+#[derive(Debug, Default, Clone)]
+pub struct ClockTracker {
+    clocks: HashMap<u64, u64>,
+}
+
+impl ClockTracker {
+    pub fn get(&self, contract_id: u64) -> Option<u64> {
+        self.clocks.get(&contract_id).copied()
+    }
+    /// forgets every contract, e.g. after a reconnect
+    pub fn reset(&mut self) {
+        self.clocks.clear();
+    }
+    /// records the message's clock, returning a `Resync` if it went backwards. a book top and
+    /// an action report for the same event share a clock, so repeats are fine, as are gaps.
+    pub fn check(&mut self, msg: &WebSocketMsg) -> Option<WebSocketMsg> {
+        let (contract_id, clock) = match msg {
+            WebSocketMsg::BookTop(bt) => (bt.contract_id, bt.clock),
+            WebSocketMsg::ActionReport(ar) => (ar.contract_id, ar.clock),
+            _ => return None,
+        };
+        let last_clock = self.clocks.insert(contract_id, clock)?;
+        if clock < last_clock {
+            // keep the newer clock so a single stray frame doesn't trigger two resyncs
+            self.clocks.insert(contract_id, last_clock);
+            return Some(WebSocketMsg::Resync {
+                contract_id,
+                last_clock,
+                clock,
+            });
+        }
+        None
+    }
 }
 
 pub struct WebSocketMsgParser();
@@ -59,30 +354,70 @@ impl WebSocketMsgParser {
     pub fn parse(msg: &OwnedMessage) -> Result<WebSocketMsg, WebSocketError> {
         match msg {
             websocket::OwnedMessage::Text(s) => {
-                if let Some(_i) = s.find("\"type\": \"book_top\"") {
-                    return Ok(WebSocketMsg::BookTop(RawBookTop::parse(s)?.sanitize()));
-                } else if let Some(_i) = s.find("\"type\": \"heartbeat\"") {
-                    return Ok(WebSocketMsg::HeartBeat(RawHeartbeat::parse(s)?));
-                } else if let Some(_i) = s.find("\"type\": \"unauth_success\"") {
-                    return Ok(WebSocketMsg::UnAuthSuccess);
-                } else if let Some(_i) = s.find("\"type\": \"meta\"") {
-                    return Ok(WebSocketMsg::SessionID("unimplemented lol".to_string()));
-                }
-
-                return Err(WebSocketError::UnknownMsgType(s.to_string()));
-            },
+                return Self::parse_text(s);
+            }
             websocket::OwnedMessage::Ping(data) => {
                 return Ok(WebSocketMsg::Ping(data.to_owned()));
-            },
+            }
             websocket::OwnedMessage::Pong(_) => {
                 return Ok(WebSocketMsg::Pong);
-            },
-            _ => {
-                println!("{:?}", msg);
-                unimplemented!("unimplemented OwnedMessage type");
+            }
+            websocket::OwnedMessage::Close(data) => {
+                return Ok(WebSocketMsg::Closed {
+                    code: data.as_ref().map(|d| d.status_code),
+                    reason: data.as_ref().map(|d| d.reason.clone()).unwrap_or_default(),
+                });
+            }
+            // the exchange only speaks JSON text frames
+            websocket::OwnedMessage::Binary(data) => {
+                return Err(WebSocketError::UnexpectedBinary(data.to_owned()));
             }
         }
     }
+    /// dispatches a text frame on its `type` field. types we don't model come back as
+    /// `WebSocketMsg::Unknown` rather than an error, a known type that fails to deserialize
+    /// is still a `MsgParsing` error.
+    pub fn parse_text(s: &str) -> Result<WebSocketMsg, WebSocketError> {
+        let msg = match RawFrame::parse(s)? {
+            RawFrame::BookTop(bt) => WebSocketMsg::BookTop(bt.sanitize()),
+            RawFrame::Heartbeat(hb) => WebSocketMsg::HeartBeat(hb),
+            RawFrame::UnauthSuccess => WebSocketMsg::UnAuthSuccess,
+            RawFrame::AuthSuccess => WebSocketMsg::AuthSuccess,
+            RawFrame::Meta(meta) => WebSocketMsg::Meta(meta.sanitize()),
+            RawFrame::OpenPositionsUpdate(p) => WebSocketMsg::Positions(p.sanitize()),
+            RawFrame::CollateralBalanceUpdate(c) => WebSocketMsg::Collateral(c.sanitize()),
+            RawFrame::Fill(f) => WebSocketMsg::Fill(f.sanitize()),
+            RawFrame::ActionReport(ar) => WebSocketMsg::ActionReport(ar.sanitize()),
+            RawFrame::Unknown => WebSocketMsg::Unknown {
+                type_name: RawFrameType::parse(s)?.type_name,
+                raw: s.to_string(),
+            },
+        };
+        Ok(msg)
+    }
+}
+
+/// every text frame type we model, tagged by the frame's `type` field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RawFrame {
+    BookTop(RawBookTop),
+    Heartbeat(RawHeartbeat),
+    UnauthSuccess,
+    AuthSuccess,
+    Meta(RawMeta),
+    OpenPositionsUpdate(RawPositionList),
+    CollateralBalanceUpdate(RawCollateralBalances),
+    Fill(RawFill),
+    ActionReport(RawActionReport),
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFrameType {
+    #[serde(rename = "type")]
+    type_name: String,
 }
 
 pub trait RawMsg<'a>
@@ -116,6 +451,32 @@ pub struct RawHeartbeat {
     interval_ms: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawMeta {
+    session_id: String,
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+/// per-connection info sent once by the exchange after connecting
+#[derive(Debug, Clone)]
+pub struct SessionMeta {
+    pub session_id: String,
+    /// every other field of the message, as sent
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl<'a> SanitizableMsg<'a> for RawMeta {
+    type OUT = SessionMeta;
+    fn sanitize(mut self) -> Self::OUT {
+        self.extra.remove("type");
+        SessionMeta {
+            session_id: self.session_id,
+            extra: self.extra,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RawBookTop {
     bid: u64,
@@ -131,10 +492,10 @@ pub struct RawBookTop {
 }
 #[derive(Debug)]
 pub struct BookTop {
-    pub bid: f64,
+    pub bid: Price,
     pub bid_size: u64,
 
-    pub ask: f64,
+    pub ask: Price,
     pub ask_size: u64,
 
     pub contract_id: u64,
@@ -147,10 +508,10 @@ impl<'a> SanitizableMsg<'a> for RawBookTop {
     type OUT = BookTop;
     fn sanitize(self) -> Self::OUT {
         BookTop {
-            bid: (self.bid as f64) / 100.0,
+            bid: Price::from_cents(self.bid),
             bid_size: self.bid_size,
 
-            ask: (self.ask as f64) / 100.0,
+            ask: Price::from_cents(self.ask),
             ask_size: self.ask_size,
 
             contract_id: self.contract_id,
@@ -161,6 +522,410 @@ impl<'a> SanitizableMsg<'a> for RawBookTop {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawActionReport {
+    mid: String,
+    contract_id: u64,
+    status_type: u64,
+    #[serde(default)]
+    status_reason: u64,
+    is_ask: bool,
+
+    price: u64,
+    size: u64,
+    #[serde(default)]
+    filled_price: u64,
+    #[serde(default)]
+    filled_size: u64,
+    #[serde(default)]
+    open_size: u64,
+
+    clock: u64,
+}
+
+/// lifecycle event of one of our orders, as reported on the `action_report` channel
+#[derive(Debug, Clone)]
+pub struct ActionReport {
+    pub mid: String,
+    pub contract_id: u64,
+    pub status: ActionStatus,
+    pub status_reason: u64,
+    pub is_ask: bool,
+
+    pub price: Price,
+    pub size: u64,
+    pub filled_price: Price,
+    pub filled_size: u64,
+    pub open_size: u64,
+
+    pub clock: u64,
+    /// the `Order::client_tag` the order was sent with. the exchange never sees it, so it's
+    /// `None` off the wire and filled in by `OrderTracker::apply`.
+    pub client_tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionStatus {
+    /// 200: the order is resting on the book
+    Inserted,
+    /// 201: the order crossed and nothing is left open
+    Filled,
+    /// 201: the order crossed, `open_size` is still resting
+    PartiallyFilled,
+    /// 202: a market order found nothing to trade against
+    NotFilled,
+    /// 203: the order was cancelled
+    Cancelled,
+    /// 204: the order was edited (cancel/replace) in place
+    Edited,
+    /// 300 and up: the exchange refused the action, see `status_reason`
+    Rejected(u64),
+    Other(u64),
+}
+
+impl ActionStatus {
+    pub fn from_code(status_type: u64, open_size: u64) -> Self {
+        match status_type {
+            200 => ActionStatus::Inserted,
+            201 if open_size > 0 => ActionStatus::PartiallyFilled,
+            201 => ActionStatus::Filled,
+            202 => ActionStatus::NotFilled,
+            203 => ActionStatus::Cancelled,
+            204 => ActionStatus::Edited,
+            code if code >= 300 => ActionStatus::Rejected(code),
+            code => ActionStatus::Other(code),
+        }
+    }
+}
+
+impl<'a> SanitizableMsg<'a> for RawActionReport {
+    type OUT = ActionReport;
+    fn sanitize(self) -> Self::OUT {
+        ActionReport {
+            status: ActionStatus::from_code(self.status_type, self.open_size),
+            mid: self.mid,
+            contract_id: self.contract_id,
+            status_reason: self.status_reason,
+            is_ask: self.is_ask,
+
+            price: Price::from_cents(self.price),
+            size: self.size,
+            filled_price: Price::from_cents(self.filled_price),
+            filled_size: self.filled_size,
+            open_size: self.open_size,
+
+            clock: self.clock,
+            client_tag: None,
+        }
+    }
+}
+
 pub struct RawOrderResponse {}
-pub struct RawBookState {}
-pub struct RawPositionList {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawPositionList {
+    positions: Vec<RawPosition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawPosition {
+    contract_id: u64,
+    size: i64,
+    #[serde(default)]
+    assigned_size: i64,
+    #[serde(default)]
+    exercised_size: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionUpdate {
+    pub contract_id: u64,
+    /// signed, negative sizes are short positions
+    pub size: i64,
+    pub assigned_size: i64,
+    pub exercised_size: i64,
+}
+
+impl<'a> SanitizableMsg<'a> for RawPosition {
+    type OUT = PositionUpdate;
+    fn sanitize(self) -> Self::OUT {
+        PositionUpdate {
+            contract_id: self.contract_id,
+            size: self.size,
+            assigned_size: self.assigned_size,
+            exercised_size: self.exercised_size,
+        }
+    }
+}
+
+impl<'a> SanitizableMsg<'a> for RawPositionList {
+    type OUT = Vec<PositionUpdate>;
+    fn sanitize(self) -> Self::OUT {
+        self.positions.into_iter().map(|p| p.sanitize()).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawCollateralBalances {
+    collateral: RawCollateral,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawCollateral {
+    available_balances: HashMap<String, i64>,
+    #[serde(default)]
+    position_locked_balances: HashMap<String, i64>,
+}
+
+/// balances are keyed by asset and denominated in the asset's smallest unit (cents, satoshis, ...)
+#[derive(Debug, Clone)]
+pub struct CollateralBalances {
+    pub available: HashMap<String, i64>,
+    pub position_locked: HashMap<String, i64>,
+}
+
+impl<'a> SanitizableMsg<'a> for RawCollateralBalances {
+    type OUT = CollateralBalances;
+    fn sanitize(self) -> Self::OUT {
+        CollateralBalances {
+            available: self.collateral.available_balances,
+            position_locked: self.collateral.position_locked_balances,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_text(s: &str) -> WebSocketMsg {
+        WebSocketMsgParser::parse(&OwnedMessage::Text(s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parse_action_reports() {
+        let msg = parse_text(
+            r#"{"type": "action_report", "mid": "7b0b8e6c2a5d4d2a8a3c0c1e5f6a7b8c", "contract_id": 22252392, "status_type": 201, "status_reason": 52, "is_ask": false, "price": 175, "size": 10, "filled_price": 170, "filled_size": 4, "open_size": 6, "clock": 1042}"#,
+        );
+        match msg {
+            WebSocketMsg::ActionReport(ar) => {
+                assert_eq!(ar.status, ActionStatus::PartiallyFilled);
+                assert_eq!(ar.filled_size, 4);
+                assert_eq!(ar.filled_price, Price::from_cents(170));
+                assert_eq!(ar.clock, 1042);
+            }
+            other => panic!("expected action report, got {:?}", other),
+        }
+
+        assert_eq!(ActionStatus::from_code(201, 0), ActionStatus::Filled);
+        assert_eq!(ActionStatus::from_code(203, 6), ActionStatus::Cancelled);
+        assert_eq!(ActionStatus::from_code(607, 0), ActionStatus::Rejected(607));
+    }
+
+    fn book_top(contract_id: u64, clock: u64) -> WebSocketMsg {
+        WebSocketMsg::BookTop(BookTop {
+            bid: Price::from_cents(100),
+            bid_size: 1,
+            ask: Price::from_cents(150),
+            ask_size: 1,
+            contract_id,
+            contract_type: 0,
+            clock,
+        })
+    }
+
+    #[test]
+    fn clock_gaps_and_regressions() {
+        let mut clocks = ClockTracker::default();
+        assert!(clocks.check(&book_top(1, 10)).is_none());
+        assert!(clocks.check(&book_top(1, 11)).is_none());
+        assert!(clocks.check(&book_top(1, 11)).is_none());
+        // other contracts have their own clocks
+        assert!(clocks.check(&book_top(2, 500)).is_none());
+
+        // gaps are normal, the clock moves on for changes that aren't sent
+        assert!(clocks.check(&book_top(1, 14)).is_none());
+        match clocks.check(&book_top(1, 12)) {
+            Some(WebSocketMsg::Resync {
+                contract_id: 1,
+                last_clock: 14,
+                clock: 12,
+            }) => {}
+            other => panic!("expected regression resync, got {:?}", other),
+        }
+        assert_eq!(clocks.get(1), Some(14));
+        assert!(clocks.check(&book_top(1, 15)).is_none());
+    }
+
+    #[test]
+    fn reconnects_after_drop() {
+        let server = websocket::sync::Server::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", server.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut server = server;
+            let mut first = server.accept().ok().unwrap().accept().unwrap();
+            first
+                .send_message(&OwnedMessage::Text(
+                    r#"{"type": "heartbeat", "timestamp": 1, "ticks": 1, "run_id": 7, "interval_ms": 1000}"#
+                        .to_string(),
+                ))
+                .unwrap();
+            first.shutdown().unwrap();
+
+            let mut second = server.accept().ok().unwrap().accept().unwrap();
+            second
+                .send_message(&OwnedMessage::Text(
+                    r#"{"type": "book_top", "bid": 100, "bid_size": 1, "ask": 125, "ask_size": 2, "contract_id": 1, "contract_type": 0, "clock": 3}"#
+                        .to_string(),
+                ))
+                .unwrap();
+            // keep the socket open until the client has read the frame
+            let _ = second.recv_message();
+        });
+
+        let mut c = WebSocketClient::connect(&endpoint).unwrap();
+        c.reconnect_policy(Some(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        }))
+        .unwrap();
+
+        assert!(matches!(c.yield_msg().unwrap(), WebSocketMsg::HeartBeat(_)));
+        match c.yield_msg().unwrap() {
+            WebSocketMsg::Reconnected { attempts: 1, .. } => {}
+            other => panic!("expected reconnect, got {:?}", other),
+        }
+        // still watching for silence, three 1s intervals
+        let timeout = c.client.stream_ref().as_tcp().read_timeout().unwrap();
+        assert_eq!(timeout, Some(Duration::from_secs(3)));
+        assert!(matches!(c.yield_msg().unwrap(), WebSocketMsg::BookTop(_)));
+
+        // without a policy, silence is the caller's to deal with
+        c.reconnect_policy(None).unwrap();
+        let timeout = c.client.stream_ref().as_tcp().read_timeout().unwrap();
+        assert_eq!(timeout, None);
+
+        drop(c);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn failed_pongs_reconnect() {
+        let server = websocket::sync::Server::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", server.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut server = server;
+            let mut first = server.accept().ok().unwrap().accept().unwrap();
+            first
+                .send_message(&OwnedMessage::Ping(b"hi".to_vec()))
+                .unwrap();
+
+            let mut second = server.accept().ok().unwrap().accept().unwrap();
+            second
+                .send_message(&OwnedMessage::Text(
+                    r#"{"type": "book_top", "bid": 100, "bid_size": 1, "ask": 125, "ask_size": 2, "contract_id": 1, "contract_type": 0, "clock": 3}"#
+                        .to_string(),
+                ))
+                .unwrap();
+            let _ = second.recv_message();
+            drop(first);
+        });
+
+        let mut c = WebSocketClient::connect(&endpoint).unwrap();
+        c.reconnect_policy(Some(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        }))
+        .unwrap();
+        // the ping still arrives, but there's no sending the pong back
+        c.client
+            .stream_ref()
+            .as_tcp()
+            .shutdown(std::net::Shutdown::Write)
+            .unwrap();
+
+        match c.yield_msg().unwrap() {
+            WebSocketMsg::Reconnected { attempts: 1, reason } => {
+                assert!(reason.contains("ping"), "{}", reason)
+            }
+            other => panic!("expected reconnect, got {:?}", other),
+        }
+        assert!(matches!(c.yield_msg().unwrap(), WebSocketMsg::BookTop(_)));
+
+        drop(c);
+        handle.join().unwrap();
+    }
+
+    fn echo_server(frames: Vec<OwnedMessage>) -> (String, thread::JoinHandle<Vec<OwnedMessage>>) {
+        let server = websocket::sync::Server::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", server.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut server = server;
+            let mut conn = server.accept().ok().unwrap().accept().unwrap();
+            for f in frames {
+                conn.send_message(&f).unwrap();
+            }
+            let mut received = Vec::new();
+            while let Ok(m) = conn.recv_message() {
+                received.push(m.clone());
+                if m.is_close() {
+                    break;
+                }
+                conn.send_message(&m).unwrap();
+            }
+            received
+        });
+        (endpoint, handle)
+    }
+
+    #[test]
+    fn server_close_is_graceful() {
+        let (endpoint, handle) = echo_server(vec![
+            OwnedMessage::Text(
+                r#"{"type": "book_top", "bid": 100, "bid_size": 1, "ask": 125, "ask_size": 2, "contract_id": 1, "contract_type": 0, "clock": 3}"#
+                    .to_string(),
+            ),
+            OwnedMessage::Close(Some(websocket::CloseData::new(1001, "going away".to_string()))),
+        ]);
+
+        let mut c = WebSocketClient::connect(&endpoint).unwrap();
+        assert!(matches!(c.yield_msg().unwrap(), WebSocketMsg::BookTop(_)));
+        match c.yield_msg().unwrap() {
+            WebSocketMsg::Closed { code, reason } => {
+                assert_eq!(code, Some(1001));
+                assert_eq!(reason, "going away");
+            }
+            other => panic!("expected close, got {:?}", other),
+        }
+        assert!(matches!(c.yield_msg(), Err(WebSocketError::Closed)));
+
+        // the close was answered rather than the socket just dropping
+        let received = handle.join().unwrap();
+        assert!(received.last().unwrap().is_close());
+    }
+
+    #[test]
+    fn binary_frames_are_rejected() {
+        let (endpoint, handle) = echo_server(vec![
+            OwnedMessage::Binary(vec![1, 2, 3]),
+            OwnedMessage::Text(
+                r#"{"type": "heartbeat", "timestamp": 1, "ticks": 1, "run_id": 7, "interval_ms": 1000}"#
+                    .to_string(),
+            ),
+        ]);
+
+        let mut c = WebSocketClient::connect(&endpoint).unwrap();
+        match c.yield_msg() {
+            Err(WebSocketError::UnexpectedBinary(data)) => assert_eq!(data, vec![1, 2, 3]),
+            other => panic!("expected binary rejection, got {:?}", other),
+        }
+        // the connection survives a rejected frame
+        assert!(matches!(c.yield_msg().unwrap(), WebSocketMsg::HeartBeat(_)));
+
+        drop(c);
+        handle.join().unwrap();
+    }
+}
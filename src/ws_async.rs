@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+
+use futures_util::stream::{self, Stream};
+use futures_util::StreamExt;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::error::WebSocketError;
+use crate::ws::{ClockTracker, SessionMeta, WebSocketMsg, WebSocketMsgParser};
+
+/// async counterpart of `WebSocketClient`, frames go through the same parser, sanitizers and
+/// clock checks. reconnecting is left to the caller: once the stream ends, connect again.
+pub struct AsyncWebSocketClient {
+    // state
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    last_clock: ClockTracker,
+    authenticated: bool,
+    closed: bool,
+    session: Option<SessionMeta>,
+    // frames held back while a `Resync` is handed out first
+    pending: VecDeque<WebSocketMsg>,
+}
+
+impl AsyncWebSocketClient {
+    /// opens an unauthenticated feed, only public channels (book tops, heartbeats) are sent
+    pub async fn connect(endpoint: &str) -> Result<Self, WebSocketError> {
+        Self::open(endpoint.to_string()).await
+    }
+    /// opens a feed authenticated with the JWT `api_key` and waits until the exchange
+    /// acknowledges it
+    pub async fn connect_authenticated(
+        endpoint: &str,
+        api_key: &str,
+    ) -> Result<Self, WebSocketError> {
+        let mut out = Self::open(format!("{}?token={}", endpoint, api_key)).await?;
+        loop {
+            match out.next_msg().await? {
+                WebSocketMsg::AuthSuccess => return Ok(out),
+                WebSocketMsg::UnAuthSuccess => {
+                    return Err(WebSocketError::AuthFailure(
+                        "exchange did not accept the api key".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+    async fn open(url: String) -> Result<Self, WebSocketError> {
+        let (stream, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(AsyncWebSocketClient {
+            stream,
+            last_clock: ClockTracker::default(),
+            authenticated: false,
+            closed: false,
+            session: None,
+            pending: VecDeque::new(),
+        })
+    }
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+    pub fn session(&self) -> Option<&SessionMeta> {
+        self.session.as_ref()
+    }
+    pub fn session_id(&self) -> Option<&str> {
+        self.session.as_ref().map(|m| m.session_id.as_str())
+    }
+    pub fn last_clock(&self, contract_id: u64) -> Option<u64> {
+        self.last_clock.get(contract_id)
+    }
+
+    /// waits for the next message, see `WebSocketClient::yield_msg`. pings are answered
+    /// by tungstenite itself on the next read.
+    pub async fn next_msg(&mut self) -> Result<WebSocketMsg, WebSocketError> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        if self.closed {
+            return Err(WebSocketError::Closed);
+        }
+        let web_msg = match self.stream.next().await {
+            Some(m) => m?,
+            None => {
+                self.closed = true;
+                return Err(WebSocketError::Closed);
+            }
+        };
+
+        let msg = match web_msg {
+            Message::Text(s) => WebSocketMsgParser::parse_text(&s)?,
+            Message::Ping(data) => WebSocketMsg::Ping(data),
+            Message::Pong(_) => WebSocketMsg::Pong,
+            Message::Close(frame) => WebSocketMsg::Closed {
+                code: frame.as_ref().map(|f| f.code.into()),
+                reason: frame.map(|f| f.reason.into_owned()).unwrap_or_default(),
+            },
+            Message::Binary(data) => return Err(WebSocketError::UnexpectedBinary(data)),
+            Message::Frame(_) => unreachable!("raw frames are never yielded when reading"),
+        };
+        match &msg {
+            WebSocketMsg::AuthSuccess => self.authenticated = true,
+            WebSocketMsg::Meta(meta) => self.session = Some(meta.clone()),
+            WebSocketMsg::Closed { .. } => {
+                // tungstenite has already queued the close reply, this flushes it
+                let _ = self.stream.close(None).await;
+                self.closed = true;
+                self.authenticated = false;
+            }
+            _ => {}
+        }
+        if let Some(resync) = self.last_clock.check(&msg) {
+            self.pending.push_back(msg);
+            return Ok(resync);
+        }
+        Ok(msg)
+    }
+
+    /// every message as a `Stream`, ending once the connection is closed
+    pub fn into_stream(self) -> impl Stream<Item = Result<WebSocketMsg, WebSocketError>> {
+        stream::unfold(self, |mut c| async move {
+            match c.next_msg().await {
+                Err(WebSocketError::Closed) => None,
+                out => Some((out, c)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+    /// accepts a single connection and sends `frames`, then waits for the client to close
+    async fn serve(frames: Vec<Message>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            for f in frames {
+                ws.send(f).await.unwrap();
+            }
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+        endpoint
+    }
+
+    #[tokio::test]
+    async fn stream_of_messages() {
+        let endpoint = serve(vec![
+            Message::Text(r#"{"type": "meta", "session_id": "abc"}"#.to_string()),
+            Message::Text(
+                r#"{"type": "book_top", "bid": 100, "bid_size": 1, "ask": 125, "ask_size": 2, "contract_id": 1, "contract_type": 0, "clock": 5}"#
+                    .to_string(),
+            ),
+            Message::Text(
+                r#"{"type": "book_top", "bid": 100, "bid_size": 1, "ask": 150, "ask_size": 2, "contract_id": 1, "contract_type": 0, "clock": 3}"#
+                    .to_string(),
+            ),
+            Message::Close(Some(CloseFrame {
+                code: CloseCode::Away,
+                reason: "going away".into(),
+            })),
+        ])
+        .await;
+
+        let client = AsyncWebSocketClient::connect(&endpoint).await.unwrap();
+        let msgs: Vec<_> = client.into_stream().collect().await;
+        let msgs: Vec<_> = msgs.into_iter().map(|m| m.unwrap()).collect();
+
+        assert_eq!(msgs.len(), 5, "{:?}", msgs);
+        assert!(matches!(&msgs[0], WebSocketMsg::Meta(m) if m.session_id == "abc"));
+        assert!(matches!(msgs[1], WebSocketMsg::BookTop(_)));
+        assert!(matches!(
+            msgs[2],
+            WebSocketMsg::Resync {
+                contract_id: 1,
+                last_clock: 5,
+                clock: 3
+            }
+        ));
+        assert!(matches!(msgs[3], WebSocketMsg::BookTop(_)));
+        assert!(matches!(
+            msgs[4],
+            WebSocketMsg::Closed {
+                code: Some(1001),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn binary_frames_are_rejected() {
+        let endpoint = serve(vec![Message::Binary(vec![1, 2, 3])]).await;
+
+        let mut client = AsyncWebSocketClient::connect(&endpoint).await.unwrap();
+        match client.next_msg().await {
+            Err(WebSocketError::UnexpectedBinary(data)) => assert_eq!(data, vec![1, 2, 3]),
+            other => panic!("expected binary rejection, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! recorded exchange frames, run through the same parser `WebSocketClient` uses
+
+use ftx_us_derivs::error::WebSocketError;
+use ftx_us_derivs::price::Price;
+use ftx_us_derivs::ws::{ActionStatus, WebSocketMsg, WebSocketMsgParser};
+
+macro_rules! fixture {
+    ($name:literal) => {
+        include_str!(concat!("fixtures/ws/", $name, ".json"))
+    };
+}
+
+fn parse(frame: &str) -> WebSocketMsg {
+    WebSocketMsgParser::parse_text(frame).unwrap()
+}
+
+#[test]
+fn book_top() {
+    match parse(fixture!("book_top")) {
+        WebSocketMsg::BookTop(bt) => {
+            assert_eq!(bt.contract_id, 22252392);
+            assert_eq!(bt.bid, Price::from_cents(4550));
+            assert_eq!(bt.bid_size, 12);
+            assert_eq!(bt.ask, Price::from_cents(4700));
+            assert_eq!(bt.ask_size, 3);
+            assert_eq!(bt.clock, 18847);
+        }
+        other => panic!("expected book top, got {:?}", other),
+    }
+}
+
+#[test]
+fn book_top_whitespace_and_field_order() {
+    for frame in [fixture!("book_top_compact"), fixture!("book_top_pretty")] {
+        assert!(
+            matches!(parse(frame), WebSocketMsg::BookTop(_)),
+            "{}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn heartbeat() {
+    assert!(matches!(
+        parse(fixture!("heartbeat")),
+        WebSocketMsg::HeartBeat(_)
+    ));
+}
+
+#[test]
+fn auth_replies() {
+    assert!(matches!(
+        parse(fixture!("auth_success")),
+        WebSocketMsg::AuthSuccess
+    ));
+    assert!(matches!(
+        parse(fixture!("unauth_success")),
+        WebSocketMsg::UnAuthSuccess
+    ));
+}
+
+#[test]
+fn meta() {
+    match parse(fixture!("meta")) {
+        WebSocketMsg::Meta(meta) => {
+            assert_eq!(meta.session_id, "5e0f8c1a-2b7d-4a4e-9c3e-0d6f1b2a3c4d");
+            assert_eq!(meta.extra["mpid"], 1337);
+            assert!(!meta.extra.contains_key("type"));
+        }
+        other => panic!("expected meta, got {:?}", other),
+    }
+}
+
+#[test]
+fn action_report() {
+    match parse(fixture!("action_report")) {
+        WebSocketMsg::ActionReport(ar) => {
+            assert_eq!(ar.mid, "4ff9c5a1e3e54b6d8c83a1bb6d0f2a17");
+            assert_eq!(ar.status, ActionStatus::Filled);
+            assert_eq!(ar.filled_size, 5);
+            assert_eq!(ar.filled_price, Price::from_cents(4700));
+            assert!(ar.is_ask);
+        }
+        other => panic!("expected action report, got {:?}", other),
+    }
+}
+
+#[test]
+fn open_positions_update() {
+    match parse(fixture!("open_positions_update")) {
+        WebSocketMsg::Positions(p) => {
+            assert_eq!(p.len(), 2);
+            assert_eq!(p[0].size, -5);
+            assert_eq!(p[1].contract_id, 22252401);
+            assert_eq!(p[1].assigned_size, 0);
+        }
+        other => panic!("expected positions, got {:?}", other),
+    }
+}
+
+#[test]
+fn collateral_balance_update() {
+    match parse(fixture!("collateral_balance_update")) {
+        WebSocketMsg::Collateral(c) => {
+            assert_eq!(c.available["BTC"], 25000000);
+            assert_eq!(c.position_locked["USD"], 23500);
+        }
+        other => panic!("expected collateral, got {:?}", other),
+    }
+}
+
+#[test]
+fn fill() {
+    match parse(fixture!("fill")) {
+        WebSocketMsg::Fill(f) => {
+            assert_eq!(f.mid, "4ff9c5a1e3e54b6d8c83a1bb6d0f2a17");
+            assert_eq!(f.price, Price::from_cents(4700));
+            assert_eq!((f.size, f.fee), (5, 125));
+            assert!(f.is_ask);
+        }
+        other => panic!("expected a fill, got {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_type_passes_through() {
+    let frame = fixture!("contract_added");
+    match parse(frame) {
+        WebSocketMsg::Unknown { type_name, raw } => {
+            assert_eq!(type_name, "contract_added");
+            assert_eq!(raw, frame);
+        }
+        other => panic!("expected unknown, got {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_known_type_is_an_error() {
+    match WebSocketMsgParser::parse_text(fixture!("book_top_truncated")) {
+        Err(WebSocketError::MsgParsing(..)) => {}
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}